@@ -0,0 +1,613 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use regex::Regex;
+
+use errors::*;
+use relabel::Labels;
+use report::{Finding, Severity};
+use usage::*;
+
+/// The parts of `alertmanager.yml` used to route alerts.
+#[derive(Deserialize, Debug, Clone)]
+pub struct AlertmanagerConfig {
+    pub route: RouteConfig,
+    #[serde(default = "Vec::new")]
+    pub inhibit_rules: Vec<InhibitRuleConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RouteConfig {
+    pub receiver: Option<String>,
+    pub group_by: Option<Vec<String>>,
+    #[serde(rename = "continue", default)]
+    pub continue_matching: bool,
+    #[serde(default = "Vec::new")]
+    pub matchers: Vec<String>,
+    /// Deprecated by `matchers`, still common.
+    #[serde(rename = "match", default)]
+    pub match_equal: BTreeMap<String, String>,
+    #[serde(default)]
+    pub match_re: BTreeMap<String, String>,
+    #[serde(default = "Vec::new")]
+    pub routes: Vec<RouteConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct InhibitRuleConfig {
+    #[serde(default = "Vec::new")]
+    pub source_matchers: Vec<String>,
+    #[serde(default)]
+    pub source_match: BTreeMap<String, String>,
+    #[serde(default)]
+    pub source_match_re: BTreeMap<String, String>,
+    #[serde(default = "Vec::new")]
+    pub target_matchers: Vec<String>,
+    #[serde(default)]
+    pub target_match: BTreeMap<String, String>,
+    #[serde(default)]
+    pub target_match_re: BTreeMap<String, String>,
+    #[serde(default = "Vec::new")]
+    pub equal: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatchOp {
+    Equal,
+    NotEqual,
+    Regex,
+    NotRegex,
+}
+
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    pub name: String,
+    pub op: MatchOp,
+    pub value: String,
+    regex: Option<Regex>,
+}
+
+impl Matcher {
+    pub fn new(name: &str, op: MatchOp, value: &str) -> Result<Matcher, String> {
+        let regex = match op {
+            // alertmanager anchors regexes at both ends
+            MatchOp::Regex | MatchOp::NotRegex => Some(
+                Regex::new(&format!("^(?:{})$", value))
+                    .map_err(|err| format!("invalid regex {:?}: {}", value, err))?,
+            ),
+            _ => None,
+        };
+
+        Ok(Matcher {
+            name: name.to_string(),
+            op,
+            value: value.to_string(),
+            regex,
+        })
+    }
+
+    /// Parse a matcher like `severity="critical"` or `team=~ops|sre`.
+    pub fn parse(matcher: &str) -> Result<Matcher, String> {
+        let op_start = matcher
+            .find(&['=', '!'][..])
+            .ok_or_else(|| format!("invalid matcher {:?}", matcher))?;
+        let (name, rest) = matcher.split_at(op_start);
+        let (op, value) = if let Some(value) = rest.strip_prefix("=~") {
+            (MatchOp::Regex, value)
+        } else if let Some(value) = rest.strip_prefix("!~") {
+            (MatchOp::NotRegex, value)
+        } else if let Some(value) = rest.strip_prefix("!=") {
+            (MatchOp::NotEqual, value)
+        } else if let Some(value) = rest.strip_prefix('=') {
+            (MatchOp::Equal, value)
+        } else {
+            return Err(format!("invalid matcher {:?}", matcher));
+        };
+
+        let value = value.trim();
+        let value = if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+            serde_json::from_str::<String>(value)
+                .map_err(|err| format!("invalid matcher {:?}: {}", matcher, err))?
+        } else {
+            value.to_string()
+        };
+
+        Matcher::new(name.trim(), op, &value)
+    }
+
+    /// Parse a list of matchers like `{severity="critical", team=~"ops|sre"}`, the braces are
+    /// optional and commas inside quoted values do not separate matchers.
+    pub fn parse_list(matchers: &str) -> Result<Vec<Matcher>, String> {
+        let matchers = matchers.trim();
+        let matchers = match (matchers.strip_prefix('{'), matchers.ends_with('}')) {
+            (Some(inner), true) => &inner[..inner.len() - 1],
+            _ => matchers,
+        };
+
+        let mut parts = vec![];
+        let mut start = 0;
+        let mut in_quotes = false;
+        let mut escaped = false;
+
+        for (index, c) in matchers.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_quotes => escaped = true,
+                '"' => in_quotes = !in_quotes,
+                ',' if !in_quotes => {
+                    parts.push(&matchers[start..index]);
+                    start = index + 1;
+                }
+                _ => (),
+            }
+        }
+        parts.push(&matchers[start..]);
+
+        // a trailing comma is allowed
+        parts
+            .into_iter()
+            .filter(|part| !part.trim().is_empty())
+            .map(Matcher::parse)
+            .collect()
+    }
+
+    /// Missing labels match like empty ones.
+    pub fn matches(&self, labels: &Labels) -> bool {
+        let value = labels.get(&self.name).map(|value| value.as_ref()).unwrap_or("");
+
+        match (self.op, self.regex.as_ref()) {
+            (MatchOp::Equal, _) => value == self.value,
+            (MatchOp::NotEqual, _) => value != self.value,
+            (MatchOp::Regex, Some(regex)) => regex.is_match(value),
+            (MatchOp::NotRegex, Some(regex)) => !regex.is_match(value),
+            _ => false,
+        }
+    }
+}
+
+fn get_matchers(
+    matchers: &[String],
+    match_equal: &BTreeMap<String, String>,
+    match_re: &BTreeMap<String, String>,
+) -> Result<Vec<Matcher>, String> {
+    let mut result = vec![];
+
+    for matcher in matchers {
+        result.extend(Matcher::parse_list(matcher)?);
+    }
+    for (name, value) in match_equal.iter() {
+        result.push(Matcher::new(name, MatchOp::Equal, value)?);
+    }
+    for (name, value) in match_re.iter() {
+        result.push(Matcher::new(name, MatchOp::Regex, value)?);
+    }
+
+    Ok(result)
+}
+
+fn matches_all(matchers: &[Matcher], labels: &Labels) -> bool {
+    matchers.iter().all(|matcher| matcher.matches(labels))
+}
+
+/// A route with the receiver and group_by inherited from its parents.
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub receiver: String,
+    pub group_by: Vec<String>,
+    pub continue_matching: bool,
+    pub matchers: Vec<Matcher>,
+    pub routes: Vec<Route>,
+}
+
+impl Route {
+    fn new(config: &RouteConfig, parent: Option<&Route>) -> Result<Route, String> {
+        let receiver = config
+            .receiver
+            .clone()
+            .or_else(|| parent.map(|parent| parent.receiver.clone()))
+            .ok_or_else(|| "the root route has no receiver".to_string())?;
+        let group_by = config
+            .group_by
+            .clone()
+            .or_else(|| parent.map(|parent| parent.group_by.clone()))
+            .unwrap_or_default();
+
+        let mut route = Route {
+            receiver,
+            group_by,
+            continue_matching: config.continue_matching,
+            matchers: get_matchers(&config.matchers, &config.match_equal, &config.match_re)?,
+            routes: vec![],
+        };
+
+        for child in config.routes.iter() {
+            let child = Route::new(child, Some(&route))?;
+            route.routes.push(child);
+        }
+
+        Ok(route)
+    }
+
+    /// The routes from this one to every route the alert ends up in, like alertmanager
+    /// descends: the first matching child wins unless it has `continue` set.
+    pub fn find_routes(&self, labels: &Labels) -> Vec<Vec<&Route>> {
+        if !matches_all(&self.matchers, labels) {
+            return vec![];
+        }
+
+        let mut paths = vec![];
+        for child in self.routes.iter() {
+            let child_paths = child.find_routes(labels);
+            let matched = !child_paths.is_empty();
+            paths.extend(child_paths);
+
+            if matched && !child.continue_matching {
+                break;
+            }
+        }
+
+        if paths.is_empty() {
+            paths.push(vec![]);
+        }
+
+        for path in paths.iter_mut() {
+            path.insert(0, self);
+        }
+
+        paths
+    }
+}
+
+pub struct InhibitRule {
+    pub source: Vec<Matcher>,
+    pub target: Vec<Matcher>,
+    pub equal: Vec<String>,
+}
+
+impl InhibitRule {
+    /// Whether the `equal` labels allow the source to inhibit the target, labels missing on
+    /// either side are set by the expression and may be equal.
+    fn may_inhibit(&self, source: &Labels, target: &Labels) -> bool {
+        self.equal.iter().all(|name| match (source.get(name), target.get(name)) {
+            (Some(source_value), Some(target_value)) => source_value == target_value,
+            _ => true,
+        })
+    }
+}
+
+/// The routing tree and inhibit rules of an alertmanager.
+pub struct Alertmanager {
+    pub route: Route,
+    pub inhibit_rules: Vec<InhibitRule>,
+}
+
+impl Alertmanager {
+    pub fn new(config: &AlertmanagerConfig) -> Result<Alertmanager, String> {
+        let route = Route::new(&config.route, None)?;
+        let inhibit_rules = config
+            .inhibit_rules
+            .iter()
+            .enumerate()
+            .map(|(index, rule)| {
+                let in_rule = |err| format!("inhibit rule {}: {}", index, err);
+
+                Ok(InhibitRule {
+                    source: get_matchers(
+                        &rule.source_matchers,
+                        &rule.source_match,
+                        &rule.source_match_re,
+                    ).map_err(in_rule)?,
+                    target: get_matchers(
+                        &rule.target_matchers,
+                        &rule.target_match,
+                        &rule.target_match_re,
+                    ).map_err(in_rule)?,
+                    equal: rule.equal.clone(),
+                })
+            }).collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Alertmanager {
+            route,
+            inhibit_rules,
+        })
+    }
+}
+
+pub fn load_alertmanager_config(path: &Path) -> Result<Alertmanager, CliError> {
+    let content = fs::read(path).map_err(|err| CliError::IoError(path.to_path_buf(), err))?;
+    let config_error = |message| CliError::AlertmanagerConfigError(path.to_path_buf(), message);
+
+    let config: AlertmanagerConfig =
+        serde_yaml::from_slice(&content).map_err(|err| config_error(err.to_string()))?;
+
+    Alertmanager::new(&config).map_err(config_error)
+}
+
+/// The static labels of an alerting rule expression: its rule labels and `alertname`.
+pub fn get_alert_labels(expression: &Expression) -> Option<Labels> {
+    let rule = expression.pointer.last()?;
+    if rule.pointer_type != "alert" {
+        return None;
+    }
+
+    let mut labels = expression.context.labels.clone();
+    labels.insert("alertname".to_string(), rule.title.clone().unwrap_or_default());
+
+    Some(labels)
+}
+
+/// Route the static labels of every alerting rule:
+/// * `alert-route`: the receiver chain and grouping of each route the alert ends up in
+/// * `default-route`: alerts no child route matches
+/// * `no-route`: alerts the matchers of the root route reject
+/// * `inhibit-source-missing`: alerts an inhibit rule targets, but no alert is its source
+/// * `inhibit-target-missing`: alerts an inhibit rule is sourced by, but no alert is its target
+///
+/// Inhibit rules only pair alerts whose static `equal` labels do not differ.
+///
+/// Labels added by the expression are not known, routes matching on them are missed.
+pub fn check_routing(expressions: &[Expression], alertmanager: &Alertmanager) -> Vec<Finding> {
+    let alerts: Vec<(&Expression, Labels)> = expressions
+        .iter()
+        .filter_map(|expression| get_alert_labels(expression).map(|labels| (expression, labels)))
+        .collect();
+    let mut findings = vec![];
+
+    for (expression, labels) in alerts.iter() {
+        let paths = alertmanager.route.find_routes(labels);
+
+        // only possible if the root route has matchers of its own
+        if paths.is_empty() {
+            findings.push(Finding::new(
+                "no-route",
+                Severity::Warning,
+                "the matchers of the root route do not match the alert".to_string(),
+                expression,
+            ));
+            continue;
+        }
+
+        for path in paths.iter() {
+            let route = path[path.len() - 1];
+            findings.push(Finding::new(
+                "alert-route",
+                Severity::Info,
+                format!(
+                    "routed to {} via {}, grouped by [{}]",
+                    route.receiver,
+                    path.iter()
+                        .map(|route| route.receiver.as_ref())
+                        .collect::<Vec<&str>>()
+                        .join(" > "),
+                    route.group_by.join(", ")
+                ),
+                expression,
+            ));
+        }
+
+        if paths.iter().all(|path| path.len() == 1) {
+            findings.push(Finding::new(
+                "default-route",
+                Severity::Warning,
+                format!(
+                    "no route matches, the alert only reaches the default receiver {}",
+                    alertmanager.route.receiver
+                ),
+                expression,
+            ));
+        }
+    }
+
+    for (index, rule) in alertmanager.inhibit_rules.iter().enumerate() {
+        let sources: Vec<&(&Expression, Labels)> = alerts
+            .iter()
+            .filter(|(_, labels)| matches_all(&rule.source, labels))
+            .collect();
+        let targets: Vec<&(&Expression, Labels)> = alerts
+            .iter()
+            .filter(|(_, labels)| matches_all(&rule.target, labels))
+            .collect();
+
+        for (expression, labels) in targets.iter() {
+            if !sources.iter().any(|(_, source)| rule.may_inhibit(source, labels)) {
+                findings.push(Finding::new(
+                    "inhibit-source-missing",
+                    Severity::Warning,
+                    format!(
+                        "inhibit rule {} targets the alert, but no alert matches its source matchers and equal labels",
+                        index
+                    ),
+                    expression,
+                ));
+            }
+        }
+
+        for (expression, labels) in sources.iter() {
+            if !targets.iter().any(|(_, target)| rule.may_inhibit(labels, target)) {
+                findings.push(Finding::new(
+                    "inhibit-target-missing",
+                    Severity::Warning,
+                    format!(
+                        "inhibit rule {} is sourced by the alert, but no alert matches its target matchers and equal labels",
+                        index
+                    ),
+                    expression,
+                ));
+            }
+        }
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> Labels {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn alertmanager(config: &str) -> Alertmanager {
+        Alertmanager::new(&serde_yaml::from_str(config).expect("valid config"))
+            .expect("valid routes")
+    }
+
+    fn alert(name: &str, rule_labels: &[(&str, &str)]) -> Expression {
+        Expression {
+            pointer: vec![Pointer::new(0, Some(name.to_string()), "alert")],
+            expression: "up == 0".to_string(),
+            context: ExpressionContext {
+                labels: labels(rule_labels),
+                ..ExpressionContext::default()
+            },
+        }
+    }
+
+    #[test]
+    fn parse_list() {
+        let cases = vec![
+            (
+                "severity=critical",
+                vec![("severity", MatchOp::Equal, "critical")],
+            ),
+            (
+                "{severity=\"critical\", team=~\"ops|sre\"}",
+                vec![
+                    ("severity", MatchOp::Equal, "critical"),
+                    ("team", MatchOp::Regex, "ops|sre"),
+                ],
+            ),
+            // commas and escaped quotes inside values
+            (
+                "summary!=\"a, \\\"b\\\"\",env!~dev,",
+                vec![
+                    ("summary", MatchOp::NotEqual, "a, \"b\""),
+                    ("env", MatchOp::NotRegex, "dev"),
+                ],
+            ),
+            ("{}", vec![]),
+        ];
+
+        for (text, expected) in cases {
+            let matchers: Vec<(String, MatchOp, String)> = Matcher::parse_list(text)
+                .expect("valid matchers")
+                .into_iter()
+                .map(|matcher| (matcher.name, matcher.op, matcher.value))
+                .collect();
+            let expected: Vec<(String, MatchOp, String)> = expected
+                .into_iter()
+                .map(|(name, op, value)| (name.to_string(), op, value.to_string()))
+                .collect();
+
+            assert_eq!(matchers, expected, "{}", text);
+        }
+
+        assert!(Matcher::parse_list("severity").is_err());
+        assert!(Matcher::parse_list("team=~\"(ops\"").is_err());
+    }
+
+    #[test]
+    fn find_routes() {
+        let alertmanager = alertmanager(
+            "route:
+               receiver: default
+               group_by: [alertname]
+               routes:
+               - receiver: audit
+                 matchers: ['severity=~\"critical|warning\"']
+                 continue: true
+               - receiver: pager
+                 matchers: ['{severity=\"critical\", team=\"ops\"}']
+                 group_by: [cluster]
+                 routes:
+                 - receiver: night
+                   match: {shift: night}
+               - receiver: ops
+                 match_re: {team: ops|sre}",
+        );
+        let cases = vec![
+            (labels(&[("team", "dev")]), vec!["default"]),
+            (labels(&[("team", "sre")]), vec!["default > ops"]),
+            // continue keeps looking for siblings, the first match without it stops
+            (
+                labels(&[("severity", "critical"), ("team", "ops")]),
+                vec!["default > audit", "default > pager"],
+            ),
+            (
+                labels(&[("severity", "warning"), ("team", "ops")]),
+                vec!["default > audit", "default > ops"],
+            ),
+            (
+                labels(&[
+                    ("severity", "critical"),
+                    ("team", "ops"),
+                    ("shift", "night"),
+                ]),
+                vec!["default > audit", "default > pager > night"],
+            ),
+        ];
+
+        for (labels, expected) in cases {
+            let paths: Vec<String> = alertmanager
+                .route
+                .find_routes(&labels)
+                .iter()
+                .map(|path| {
+                    path.iter()
+                        .map(|route| route.receiver.as_ref())
+                        .collect::<Vec<&str>>()
+                        .join(" > ")
+                }).collect();
+
+            assert_eq!(paths, expected, "{:?}", labels);
+        }
+
+        let night = &alertmanager.route.routes[1].routes[0];
+        assert_eq!(night.group_by, vec!["cluster"]);
+    }
+
+    #[test]
+    fn check_routing() {
+        let alertmanager = alertmanager(
+            "
+             route:
+               receiver: default
+               matchers: [env=prod]
+               routes:
+               - receiver: ops
+                 match: {team: ops}
+             inhibit_rules:
+             - source_matchers: [alertname=ClusterDown]
+               target_matchers: [severity=warning]
+               equal: [cluster]",
+        );
+        let expressions = vec![
+            alert("OpsDown", &[("env", "prod"), ("team", "ops")]),
+            alert("DevDown", &[("env", "prod"), ("severity", "warning")]),
+            alert("StagingDown", &[("env", "staging")]),
+        ];
+
+        let findings: Vec<(String, String)> = super::check_routing(&expressions, &alertmanager)
+            .into_iter()
+            .map(|finding| {
+                let alert = finding.pointer[0].title.clone().unwrap_or_default();
+                (finding.rule_id, alert)
+            }).collect();
+        let expected: Vec<(String, String)> = vec![
+            ("alert-route", "OpsDown"),
+            ("alert-route", "DevDown"),
+            ("default-route", "DevDown"),
+            ("no-route", "StagingDown"),
+            ("inhibit-source-missing", "DevDown"),
+        ].into_iter()
+        .map(|(rule_id, alert)| (rule_id.to_string(), alert.to_string()))
+        .collect();
+
+        assert_eq!(findings, expected);
+    }
+}
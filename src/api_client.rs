@@ -0,0 +1,461 @@
+use std::cmp;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::future::{self, Loop};
+use futures::Future;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, ACCEPT, RETRY_AFTER};
+use serde::de::DeserializeOwned;
+use tokio::timer::Delay;
+
+use errors::ClientError;
+use http_client::{HttpClient, Query, ResponseFuture, DEFAULT_TIMEOUT_SECS};
+use retry::{is_retryable_status, RateLimiter, RetryPolicy};
+use tls::TlsConfig;
+
+/// The api flavour decides how error responses are decoded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Api {
+    Prometheus,
+    Grafana,
+}
+
+impl Api {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Api::Prometheus => "prometheus",
+            Api::Grafana => "grafana",
+        }
+    }
+}
+
+/// The `{status, data, errorType, error, warnings}` envelope of the prometheus http api.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PrometheusEnvelope<T> {
+    status: String,
+    data: Option<T>,
+    error_type: Option<String>,
+    error: Option<String>,
+    #[serde(default = "Vec::new")]
+    warnings: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GrafanaErrorBody {
+    message: String,
+}
+
+/// Connection settings of a single endpoint.
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    pub timeout: Duration,
+    pub retry: RetryPolicy,
+    pub requests_per_second: Option<f64>,
+    pub tls: TlsConfig,
+}
+
+impl Default for ClientOptions {
+    fn default() -> ClientOptions {
+        ClientOptions {
+            timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+            retry: RetryPolicy::default(),
+            requests_per_second: None,
+            tls: TlsConfig::default(),
+        }
+    }
+}
+
+/// Clients that are built on top of an `ApiClient`.
+pub trait FromApiClient: Sized {
+    const API: Api;
+
+    fn from_api_client(client: ApiClient) -> Self;
+}
+
+/// Builder for the prometheus and grafana clients.
+///
+/// Invalid header values are reported by `build`.
+pub struct ClientBuilder<C> {
+    url: String,
+    headers: HeaderMap,
+    options: ClientOptions,
+    error: Option<ClientError>,
+    client: PhantomData<C>,
+}
+
+impl<C: FromApiClient> ClientBuilder<C> {
+    pub fn new<U: Into<String>>(url: U) -> ClientBuilder<C> {
+        ClientBuilder {
+            url: url.into(),
+            headers: HeaderMap::new(),
+            options: ClientOptions::default(),
+            error: None,
+            client: PhantomData,
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> ClientBuilder<C> {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|err| ClientError::RequestError(err.to_string()));
+        let value =
+            HeaderValue::from_str(value).map_err(|err| ClientError::RequestError(err.to_string()));
+
+        match (name, value) {
+            (Ok(name), Ok(value)) => {
+                self.headers.insert(name, value);
+            }
+            (Err(err), _) | (_, Err(err)) => self.error = Some(err),
+        }
+
+        self
+    }
+
+    pub fn bearer_token(self, token: &str) -> ClientBuilder<C> {
+        self.header("Authorization", &format!("Bearer {}", token))
+    }
+
+    pub fn basic_auth(self, username: &str, password: &str) -> ClientBuilder<C> {
+        let credentials = base64::encode(&format!("{}:{}", username, password));
+
+        self.header("Authorization", &format!("Basic {}", credentials))
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> ClientBuilder<C> {
+        self.options.timeout = timeout;
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> ClientBuilder<C> {
+        self.options.retry = retry;
+        self
+    }
+
+    pub fn rate_limit(mut self, requests_per_second: f64) -> ClientBuilder<C> {
+        self.options.requests_per_second = Some(requests_per_second);
+        self
+    }
+
+    pub fn tls(mut self, tls: TlsConfig) -> ClientBuilder<C> {
+        self.options.tls = tls;
+        self
+    }
+
+    /// Replace timeout, retry policy, rate limit and tls settings at once.
+    pub fn options(mut self, options: ClientOptions) -> ClientBuilder<C> {
+        self.options = options;
+        self
+    }
+
+    pub fn build(self) -> Result<C, ClientError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+
+        ApiClient::new(C::API, self.url, Some(self.headers), &self.options)
+            .map(C::from_api_client)
+    }
+}
+
+/// A future resolving to a decoded response.
+pub type ApiFuture<T> = Box<Future<Item = T, Error = ClientError> + Send>;
+
+/// Typed json client shared by the prometheus and grafana clients.
+///
+/// Requests are futures, clones share connections and the rate limit. The client is `Send` and
+/// `Sync`, so it can be shared across threads.
+#[derive(Clone)]
+pub struct ApiClient {
+    api: Api,
+    http_client: HttpClient,
+    retry: RetryPolicy,
+    rate_limiter: Option<Arc<Mutex<RateLimiter>>>,
+}
+
+impl ApiClient {
+    pub fn new<U: Into<String>>(
+        api: Api,
+        url: U,
+        headers: Option<HeaderMap>,
+        options: &ClientOptions,
+    ) -> Result<ApiClient, ClientError> {
+        let mut default_headers = HeaderMap::new();
+        default_headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+
+        if let Some(headers) = headers {
+            for (key, value) in headers.iter() {
+                default_headers.insert(key, value.clone());
+            }
+        }
+
+        HttpClient::new(url, Some(default_headers), Some(options.timeout), &options.tls).map(
+            |http_client| ApiClient {
+                api,
+                http_client,
+                retry: options.retry.clone(),
+                rate_limiter: options
+                    .requests_per_second
+                    .map(|rate| Arc::new(Mutex::new(RateLimiter::new(rate)))),
+            },
+        )
+    }
+
+    /// GET `path` and return the raw body of a successful response.
+    pub fn get_text(&self, path: &str, params: Option<&Query>) -> ApiFuture<String> {
+        let api_client = self.clone();
+
+        Box::new(self.get(path, params).and_then(move |response| {
+            let status = response.status().as_u16();
+
+            if !response.status().is_success() {
+                return Err(api_client.error_from_body(status, response.body()));
+            }
+
+            Ok(response.into_body())
+        }))
+    }
+
+    /// GET `path` and deserialize the json body of a successful response.
+    pub fn get_json<T>(&self, path: &str, params: Option<&Query>) -> ApiFuture<T>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let client = self.api.name();
+
+        Box::new(self.get_text(path, params).and_then(move |body| {
+            serde_json::from_str(&body).map_err(|err| ClientError::DeserializeError { client, err })
+        }))
+    }
+
+    /// GET `path` from the prometheus http api and unwrap the `data` of the response envelope.
+    pub fn get_data<T>(&self, path: &str, params: Option<&Query>) -> ApiFuture<T>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let api_client = self.clone();
+
+        Box::new(
+            self.get(path, params)
+                .and_then(move |response| api_client.decode_envelope(response)),
+        )
+    }
+
+    fn decode_envelope<T: DeserializeOwned>(
+        &self,
+        response: hyper::Response<String>,
+    ) -> Result<T, ClientError> {
+        let client = self.api.name();
+        let status = response.status().as_u16();
+
+        let envelope: PrometheusEnvelope<T> = match serde_json::from_str(response.body()) {
+            Ok(envelope) => envelope,
+            Err(_) if !response.status().is_success() => {
+                return Err(self.error_from_body(status, response.body()))
+            }
+            Err(err) => return Err(ClientError::DeserializeError { client, err }),
+        };
+
+        for warning in envelope.warnings.iter() {
+            warn!("{} warning: {}", client, warning);
+        }
+
+        if envelope.status != "success" {
+            let PrometheusEnvelope {
+                status: envelope_status,
+                error_type,
+                error,
+                ..
+            } = envelope;
+
+            return Err(to_client_error(
+                client,
+                status,
+                error_type.as_ref().map(|error_type| error_type.as_ref()),
+                error.unwrap_or(envelope_status),
+            ));
+        }
+
+        envelope.data.ok_or_else(|| ClientError::ApiError {
+            client,
+            status,
+            message: "response has no data".to_string(),
+        })
+    }
+
+    /// GET with rate limiting, retrying transport errors, timeouts, 429 and 5xx responses.
+    fn get(&self, path: &str, params: Option<&Query>) -> ResponseFuture {
+        let api_client = self.clone();
+        let path = path.to_owned();
+        let params: Vec<(String, String)> = params
+            .map(|params| {
+                params
+                    .iter()
+                    .map(|&(key, value)| (key.to_owned(), value.to_owned()))
+                    .collect()
+            }).unwrap_or_else(Vec::new);
+
+        Box::new(future::loop_fn(0, move |attempt| {
+            let retry_client = api_client.clone();
+            let request_client = api_client.clone();
+            let path = path.clone();
+            let params = params.clone();
+
+            api_client
+                .wait_for_rate_limit()
+                .and_then(move |_| {
+                    let query: Vec<(&str, &str)> = params
+                        .iter()
+                        .map(|&(ref key, ref value)| (key.as_str(), value.as_str()))
+                        .collect();
+
+                    request_client
+                        .http_client
+                        .get(path.as_str(), Some(&query), None)
+                        .then(move |result| retry_client.next_attempt(attempt, &path, result))
+                })
+        }))
+    }
+
+    fn wait_for_rate_limit(&self) -> ApiFuture<()> {
+        let delay = match self.rate_limiter {
+            Some(ref rate_limiter) => rate_limiter
+                .lock()
+                .expect("rate limiter lock is poisoned")
+                .reserve(),
+            None => return Box::new(future::ok(())),
+        };
+
+        Box::new(
+            Delay::new(Instant::now() + delay)
+                .map_err(|err| ClientError::RequestError(err.to_string())),
+        )
+    }
+
+    fn next_attempt(
+        &self,
+        attempt: u32,
+        path: &str,
+        result: Result<hyper::Response<String>, ClientError>,
+    ) -> ApiFuture<Loop<hyper::Response<String>, u32>> {
+        let client = self.api.name();
+        let retryable = match result {
+            Ok(ref response) => is_retryable_status(response.status().as_u16()),
+            Err(ClientError::TimeoutError) | Err(ClientError::RequestError(_)) => true,
+            Err(_) => false,
+        };
+
+        if !retryable || attempt >= self.retry.max_retries {
+            return Box::new(future::result(result.map(Loop::Break).map_err(
+                |err| match err {
+                    ClientError::TimeoutError => ClientError::Timeout {
+                        client,
+                        status: None,
+                        message: "request timed out".to_string(),
+                    },
+                    err => err,
+                },
+            )));
+        }
+
+        let mut delay = self.retry.backoff(attempt);
+        if let Ok(ref response) = result {
+            if let Some(retry_after) = get_retry_after(response) {
+                delay = cmp::max(delay, cmp::min(retry_after, self.retry.max_backoff));
+            }
+        }
+
+        warn!(
+            "{} request to {} failed (attempt {}), retrying in {:?}",
+            client,
+            path,
+            attempt + 1,
+            delay
+        );
+
+        Box::new(
+            Delay::new(Instant::now() + delay)
+                .map(move |_| Loop::Continue(attempt + 1))
+                .map_err(|err| ClientError::RequestError(err.to_string())),
+        )
+    }
+
+    fn error_from_body(&self, status: u16, body: &str) -> ClientError {
+        let client = self.api.name();
+
+        match self.api {
+            Api::Prometheus => match serde_json::from_str::<PrometheusEnvelope<()>>(body) {
+                Ok(envelope) => to_client_error(
+                    client,
+                    status,
+                    envelope.error_type.as_ref().map(|error_type| error_type.as_ref()),
+                    envelope.error.unwrap_or_else(|| body.to_owned()),
+                ),
+                Err(_) => to_client_error(client, status, None, body.to_owned()),
+            },
+            Api::Grafana => {
+                let message = serde_json::from_str::<GrafanaErrorBody>(body)
+                    .map(|error| error.message)
+                    .unwrap_or_else(|_| body.to_owned());
+
+                to_client_error(client, status, None, message)
+            }
+        }
+    }
+}
+
+/// The `Retry-After` header, only the delay-seconds form is supported.
+fn get_retry_after(response: &hyper::Response<String>) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+/// Map a failed response to a `ClientError`, prometheus' `errorType` takes precedence over the status.
+fn to_client_error(
+    client: &'static str,
+    status: u16,
+    error_type: Option<&str>,
+    message: String,
+) -> ClientError {
+    match (error_type, status) {
+        (Some("timeout"), _) | (None, 504) => ClientError::Timeout {
+            client,
+            status: Some(status),
+            message,
+        },
+        (Some("bad_data"), _) | (None, 400) | (None, 422) => ClientError::BadQuery {
+            client,
+            status,
+            message,
+        },
+        (_, 401) | (_, 403) => ClientError::Unauthorized {
+            client,
+            status,
+            message,
+        },
+        (_, 404) => ClientError::NotFound {
+            client,
+            status,
+            message,
+        },
+        (_, 429) => ClientError::TooManyRequests {
+            client,
+            status,
+            message,
+        },
+        (_, 500..=599) => ClientError::ServerError {
+            client,
+            status,
+            message,
+        },
+        _ => ClientError::ApiError {
+            client,
+            status,
+            message,
+        },
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use hyper::{Method, StatusCode};
+use serde::Serialize;
+use url::form_urlencoded;
+
+use catalog::MetricCatalog;
+use errors::*;
+use grafana::parse_dashboard;
+use rules::parse_rule_file;
+use sources::{get_expressions_from_sources, ExpressionSource};
+use usage::*;
+
+/// Metric catalog and where-used index, refreshed in the background.
+pub struct ApiState {
+    pub catalog: Box<MetricCatalog>,
+    pub where_used: BTreeMap<String, Vec<MetricsUsage>>,
+}
+
+pub fn load_state(
+    sources: &[Box<ExpressionSource>],
+    catalog: Box<MetricCatalog>,
+) -> Result<ApiState, CliError> {
+    let usages = get_used_metrics_from_expressions(&get_expressions_from_sources(sources)?);
+
+    let mut where_used: BTreeMap<String, Vec<MetricsUsage>> = BTreeMap::new();
+    for usage in usages {
+        for metric in usage.metrics.iter() {
+            where_used
+                .entry(metric.clone())
+                .or_insert_with(Vec::new)
+                .push(usage.clone());
+        }
+    }
+
+    Ok(ApiState {
+        catalog,
+        where_used,
+    })
+}
+
+#[derive(Deserialize, Debug)]
+struct ExpressionRequest {
+    expression: String,
+}
+
+#[derive(Serialize, Debug)]
+struct ExpressionResponse {
+    expression: String,
+    valid: bool,
+    metrics: BTreeSet<String>,
+    invalid_metrics: BTreeSet<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct ValidationResponse {
+    invalid: Vec<MetricsUsage>,
+    parse_errors: Vec<Expression>,
+}
+
+#[derive(Serialize, Debug)]
+struct WhereUsedResponse {
+    metric: String,
+    usages: Vec<MetricsUsage>,
+}
+
+#[derive(Serialize, Debug)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// Route a request to its endpoint, returns the status and the json body.
+pub fn handle(
+    state: &ApiState,
+    method: &Method,
+    path: &str,
+    query: Option<&str>,
+    body: &[u8],
+) -> (StatusCode, String) {
+    match (method, path) {
+        (&Method::POST, "/api/v1/validate/expression") => validate_expression(state, body),
+        (&Method::POST, "/api/v1/validate/dashboard") => validate_dashboard(state, body),
+        (&Method::POST, "/api/v1/validate/rules") => validate_rules(state, body),
+        (&Method::GET, "/api/v1/where-used") => where_used(state, query),
+        _ => error(StatusCode::NOT_FOUND, "not found".to_string()),
+    }
+}
+
+fn validate_expression(state: &ApiState, body: &[u8]) -> (StatusCode, String) {
+    let request: ExpressionRequest = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(err) => return error(StatusCode::BAD_REQUEST, err.to_string()),
+    };
+
+    let metrics = match get_used_metrics_from_expression(&request.expression) {
+        Ok(metrics) => metrics,
+        Err(err) => return error(StatusCode::UNPROCESSABLE_ENTITY, err.to_string()),
+    };
+
+    let invalid_metrics: BTreeSet<String> = metrics
+        .iter()
+        .filter(|metric| !state.catalog.has_metric(metric))
+        .cloned()
+        .collect();
+
+    ok(&ExpressionResponse {
+        expression: request.expression,
+        valid: invalid_metrics.is_empty(),
+        metrics: metrics.into_iter().collect(),
+        invalid_metrics,
+    })
+}
+
+fn validate_dashboard(state: &ApiState, body: &[u8]) -> (StatusCode, String) {
+    let dashboard = match parse_dashboard(body) {
+        Ok(dashboard) => dashboard,
+        Err(err) => return error(StatusCode::BAD_REQUEST, err.to_string()),
+    };
+
+    validate_expressions(state, get_expressions_from_dashboard(&dashboard))
+}
+
+fn validate_rules(state: &ApiState, body: &[u8]) -> (StatusCode, String) {
+    match parse_rule_file(body) {
+        Ok(rule_file) => validate_expressions(state, get_expressions_from_rule_file(&rule_file)),
+        Err(err) => error(StatusCode::BAD_REQUEST, err.to_string()),
+    }
+}
+
+fn validate_expressions(state: &ApiState, expressions: Vec<Expression>) -> (StatusCode, String) {
+    let usages = get_used_metrics_from_expressions(&expressions);
+
+    ok(&ValidationResponse {
+        invalid: get_invalid_usages(&usages, state.catalog.as_ref()),
+        parse_errors: get_parse_errors(expressions),
+    })
+}
+
+fn where_used(state: &ApiState, query: Option<&str>) -> (StatusCode, String) {
+    let metric = query.and_then(|query| {
+        form_urlencoded::parse(query.as_bytes())
+            .find(|&(ref key, _)| key == "metric")
+            .map(|(_, value)| value.into_owned())
+    });
+
+    match metric {
+        Some(metric) => {
+            let usages = state.where_used.get(&metric).cloned().unwrap_or_default();
+
+            ok(&WhereUsedResponse { metric, usages })
+        }
+        None => error(
+            StatusCode::BAD_REQUEST,
+            "the metric query parameter is required".to_string(),
+        ),
+    }
+}
+
+fn ok<T: Serialize>(value: &T) -> (StatusCode, String) {
+    match serde_json::to_string(value) {
+        Ok(body) => (StatusCode::OK, body),
+        Err(err) => error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+    }
+}
+
+fn error(status: StatusCode, message: String) -> (StatusCode, String) {
+    let body = serde_json::to_string(&ErrorResponse { error: message })
+        .unwrap_or_else(|_| "{}".to_string());
+
+    (status, body)
+}
@@ -17,13 +17,28 @@ fn main() {
         commands: vec![
             Box::new(GetValuesCmd {}),
             Box::new(ValidateDashboardCmd {}),
+            Box::new(ValidateDashboardsCmd {}),
             Box::new(GetAlertsCmd {}),
+            Box::new(ServeCmd {}),
+            Box::new(ServeApiCmd {}),
+            Box::new(ExportCatalogCmd {}),
+            Box::new(DiffCatalogsCmd {}),
+            Box::new(ValidateCmd {}),
+            Box::new(ReportSchemaCmd {}),
+            Box::new(PredictRelabelCmd {}),
+            Box::new(CheckRoutingCmd {}),
+            Box::new(LintCatalogCmd {}),
+            Box::new(CheckRecordingRulesCmd {}),
+            Box::new(ExportGraphCmd {}),
         ],
     };
 
-    let result = app
-        .run()
-        .and_then(|value| serde_json::to_string_pretty(&value).map_err(CliError::SerdeError));
+    let result = app.run().and_then(|output| match output {
+        CommandOutput::Json(value) => {
+            serde_json::to_string_pretty(&value).map_err(CliError::SerdeError)
+        }
+        CommandOutput::Text(text) => Ok(text),
+    });
 
     let exit_code = match result {
         Ok(result) => {
@@ -0,0 +1,323 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rand::{thread_rng, Rng};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use errors::*;
+use prometheus::{MetricMetadata, PrometheusClient};
+
+pub const DEFAULT_TTL_SECS: u64 = 3600;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CacheMode {
+    /// Use entries younger than the ttl, fetch and store everything else.
+    ReadWrite,
+    /// Ignore existing entries, fetch and store everything.
+    Refresh,
+    /// Never fetch, use entries regardless of their age.
+    Offline,
+}
+
+/// File cache for api responses, with a directory per endpoint url.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+    ttl: Duration,
+    mode: CacheMode,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CacheEntry<T> {
+    url: String,
+    created_at: u64,
+    value: T,
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf, ttl: Duration, mode: CacheMode) -> Cache {
+        Cache { dir, ttl, mode }
+    }
+
+    /// The same cache in another mode.
+    pub fn with_mode(self, mode: CacheMode) -> Cache {
+        Cache { mode, ..self }
+    }
+
+    /// `$XDG_CACHE_HOME/promqueen` or the platform equivalent.
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("promqueen"))
+    }
+
+    /// Return the cached value of `key` for `url` or store the result of `fetch`.
+    pub fn get_or_fetch<T, F>(&self, url: &str, key: &str, fetch: F) -> Result<T, CliError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Result<T, ClientError>,
+    {
+        let path = self.path(url, key);
+
+        if self.mode != CacheMode::Refresh {
+            if let Some(entry) = read_entry::<T>(&path) {
+                if self.mode == CacheMode::Offline || !self.is_expired(&entry) {
+                    debug!("using cached {} of {}", key, url);
+                    return Ok(entry.value);
+                }
+            }
+        }
+
+        if self.mode == CacheMode::Offline {
+            return Err(CliError::CacheMiss(key.to_string(), url.to_string()));
+        }
+
+        let value = fetch().map_err(CliError::ClientError)?;
+        let entry = CacheEntry {
+            url: url.to_string(),
+            created_at: unix_time(SystemTime::now()),
+            value,
+        };
+
+        if let Err(err) = write_entry(&path, &entry) {
+            warn!("could not write cache {}: {:?}", path.display(), err);
+        }
+
+        Ok(entry.value)
+    }
+
+    fn is_expired<T>(&self, entry: &CacheEntry<T>) -> bool {
+        unix_time(SystemTime::now()).saturating_sub(entry.created_at) > self.ttl.as_secs()
+    }
+
+    fn path(&self, url: &str, key: &str) -> PathBuf {
+        self.dir
+            .join(to_file_name(url.trim_end_matches('/')))
+            .join(format!("{}.json", to_file_name(key)))
+    }
+}
+
+/// Percent-encode everything but alphanumerics, `-`, `.` and `_`, so distinct values never
+/// share a file.
+fn to_file_name(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        }).collect()
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs())
+        .unwrap_or(0)
+}
+
+/// Missing or unreadable entries are cache misses.
+fn read_entry<T: DeserializeOwned>(path: &Path) -> Option<CacheEntry<T>> {
+    File::open(path)
+        .ok()
+        .and_then(|file| serde_json::from_reader(file).ok())
+}
+
+fn write_entry<T: Serialize>(path: &Path, entry: &CacheEntry<T>) -> Result<(), CliError> {
+    let io_error = |err| CliError::IoError(path.to_path_buf(), err);
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(io_error)?;
+    }
+
+    // write to a temporary file of this writer first, so concurrent runs never read partial
+    // entries or write into each other's files
+    let tmp_path = path.with_extension(format!(
+        "json.{}-{:08x}.tmp",
+        process::id(),
+        thread_rng().gen::<u32>()
+    ));
+    let file = File::create(&tmp_path).map_err(io_error)?;
+    let result = serde_json::to_writer(file, entry)
+        .map_err(CliError::SerdeError)
+        .and_then(|_| fs::rename(&tmp_path, path).map_err(io_error));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+
+    result
+}
+
+/// Prometheus client which keeps its metric catalog in a `Cache`.
+pub struct CachedPrometheusClient {
+    url: String,
+    client: PrometheusClient,
+    cache: Cache,
+}
+
+impl CachedPrometheusClient {
+    pub fn new(url: String, client: PrometheusClient, cache: Cache) -> CachedPrometheusClient {
+        CachedPrometheusClient { url, client, cache }
+    }
+
+    pub fn client(&self) -> &PrometheusClient {
+        &self.client
+    }
+
+    pub fn get_label_values(&self, label: String) -> Result<Vec<String>, CliError> {
+        let key = format!("label-values-{}", label);
+
+        self.cache
+            .get_or_fetch(&self.url, &key, || self.client.get_label_values(label))
+    }
+
+    pub fn get_metric_names(&self) -> Result<Vec<String>, CliError> {
+        self.get_label_values("__name__".to_string())
+    }
+
+    pub fn get_metadata(&self) -> Result<BTreeMap<String, Vec<MetricMetadata>>, CliError> {
+        self.cache
+            .get_or_fetch(&self.url, "metadata", || self.client.get_metadata())
+    }
+
+    /// The label names of every metric, looked up from its series.
+    pub fn get_label_sets(
+        &self,
+        concurrency: usize,
+    ) -> Result<BTreeMap<String, BTreeSet<String>>, CliError> {
+        let metrics = self.get_metric_names()?;
+
+        let key = format!("label-sets-{}s", self.client.series_window().as_secs());
+
+        self.cache.get_or_fetch(&self.url, &key, || {
+            let matchers = metrics
+                .iter()
+                .map(|metric| format!("{{__name__=\"{}\"}}", metric))
+                .collect();
+            let series = self.client.get_series(matchers, concurrency)?;
+
+            Ok(metrics
+                .iter()
+                .zip(series)
+                .map(|(metric, series)| {
+                    let label_names = series
+                        .iter()
+                        .flat_map(|labels| labels.keys())
+                        .filter(|name| *name != "__name__")
+                        .cloned()
+                        .collect();
+
+                    (metric.clone(), label_names)
+                }).collect())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn test_dir(name: &str) -> PathBuf {
+        env::temp_dir().join(format!(
+            "promqueen-cache-{}-{}-{:08x}",
+            name,
+            process::id(),
+            thread_rng().gen::<u32>()
+        ))
+    }
+
+    #[test]
+    fn get_or_fetch() {
+        let url = "http://prometheus:9090/";
+        let now = unix_time(SystemTime::now());
+        // (mode, age of the cached entry, expected value, None for a cache miss)
+        let cases = vec![
+            (CacheMode::ReadWrite, Some(0), Some("cached")),
+            (CacheMode::ReadWrite, Some(120), Some("fetched")),
+            (CacheMode::ReadWrite, None, Some("fetched")),
+            (CacheMode::Refresh, Some(0), Some("fetched")),
+            (CacheMode::Refresh, None, Some("fetched")),
+            (CacheMode::Offline, Some(120), Some("cached")),
+            (CacheMode::Offline, None, None),
+        ];
+
+        for case in cases {
+            let (mode, age, expected) = case;
+            let dir = test_dir("get-or-fetch");
+            let cache = Cache::new(dir.clone(), Duration::from_secs(60), mode);
+
+            if let Some(age) = age {
+                let entry = CacheEntry {
+                    url: url.to_string(),
+                    created_at: now - age,
+                    value: "cached".to_string(),
+                };
+                write_entry(&cache.path(url, "key"), &entry).expect("writable cache");
+            }
+
+            let value = cache
+                .get_or_fetch(url, "key", || Ok("fetched".to_string()))
+                .ok();
+            assert_eq!(value, expected.map(|value| value.to_string()), "{:?}", case);
+
+            // fetched values are stored, and no temporary files are left behind
+            if expected == Some("fetched") {
+                let entry = read_entry::<String>(&cache.path(url, "key")).expect("stored entry");
+                assert_eq!(entry.value, "fetched", "{:?}", case);
+            }
+            let files = fs::read_dir(dir.join(to_file_name("http://prometheus:9090")))
+                .map(|entries| entries.count())
+                .unwrap_or(0);
+            assert_eq!(files, if expected.is_some() { 1 } else { 0 }, "{:?}", case);
+
+            let _ = fs::remove_dir_all(&dir);
+        }
+    }
+
+    #[test]
+    fn path() {
+        let cache = Cache::new(
+            PathBuf::from("/cache"),
+            Duration::from_secs(60),
+            CacheMode::ReadWrite,
+        );
+        let cases = vec![
+            (
+                "http://a:9090",
+                "metadata",
+                "/cache/http%3A%2F%2Fa%3A9090/metadata.json",
+            ),
+            // a trailing slash does not change the endpoint
+            (
+                "http://a:9090/",
+                "metadata",
+                "/cache/http%3A%2F%2Fa%3A9090/metadata.json",
+            ),
+            (
+                "http://a:9090",
+                "label-values-__name__",
+                "/cache/http%3A%2F%2Fa%3A9090/label-values-__name__.json",
+            ),
+            (
+                "http://a:9090",
+                "a/b c",
+                "/cache/http%3A%2F%2Fa%3A9090/a%2Fb%20c.json",
+            ),
+        ];
+
+        for (url, key, expected) in cases {
+            assert_eq!(
+                cache.path(url, key),
+                PathBuf::from(expected),
+                "{} {}",
+                url,
+                key
+            );
+        }
+    }
+}
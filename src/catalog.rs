@@ -0,0 +1,230 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use cache::CachedPrometheusClient;
+use errors::*;
+use prometheus::{MetricMetadata, PrometheusClient};
+use snapshot::CatalogSnapshot;
+
+/// The metrics known to exist, validators look metrics up here.
+pub trait MetricCatalog: Send + Sync {
+    /// Every metric of the catalog.
+    fn metric_names(&self) -> BTreeSet<String>;
+
+    fn has_metric(&self, metric: &str) -> bool;
+
+    /// Label names of the metric, `None` if the metric is unknown or the catalog has no labels.
+    fn get_labels(&self, metric: &str) -> Option<BTreeSet<String>>;
+
+    /// Types of the metric from its metadata, e.g. `counter`.
+    fn get_types(&self, metric: &str) -> BTreeSet<String>;
+
+    /// Unix time the metric was last seen, `None` if it is unknown.
+    fn last_seen(&self, metric: &str) -> Option<u64>;
+
+    /// Jobs exposing the metric, empty if the catalog does not know them.
+    fn get_jobs(&self, metric: &str) -> BTreeSet<String>;
+}
+
+/// The catalog of a live prometheus.
+pub struct PrometheusCatalog {
+    names: BTreeSet<String>,
+    metadata: BTreeMap<String, Vec<MetricMetadata>>,
+    label_sets: Option<BTreeMap<String, BTreeSet<String>>>,
+    loaded_at: u64,
+}
+
+impl PrometheusCatalog {
+    /// Load metric names and metadata, the labels are only loaded by `load_cached_with_labels`.
+    pub fn load(client: &PrometheusClient) -> Result<PrometheusCatalog, CliError> {
+        let names = client
+            .get_label_values("__name__".to_string())
+            .map_err(CliError::ClientError)?;
+        let metadata = client.get_metadata().map_err(CliError::ClientError);
+
+        Ok(PrometheusCatalog::new(names, or_no_metadata(metadata), None))
+    }
+
+    pub fn load_cached(client: &CachedPrometheusClient) -> Result<PrometheusCatalog, CliError> {
+        let names = client.get_metric_names()?;
+
+        Ok(PrometheusCatalog::new(
+            names,
+            or_no_metadata(client.get_metadata()),
+            None,
+        ))
+    }
+
+    /// Like `load_cached`, with the label names of every metric looked up from its series.
+    pub fn load_cached_with_labels(
+        client: &CachedPrometheusClient,
+        concurrency: usize,
+    ) -> Result<PrometheusCatalog, CliError> {
+        let mut catalog = PrometheusCatalog::load_cached(client)?;
+        catalog.label_sets = Some(client.get_label_sets(concurrency)?);
+
+        Ok(catalog)
+    }
+
+    fn new(
+        names: Vec<String>,
+        metadata: BTreeMap<String, Vec<MetricMetadata>>,
+        label_sets: Option<BTreeMap<String, BTreeSet<String>>>,
+    ) -> PrometheusCatalog {
+        PrometheusCatalog {
+            names: names.into_iter().collect(),
+            metadata,
+            label_sets,
+            loaded_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since_epoch| since_epoch.as_secs())
+                .unwrap_or(0),
+        }
+    }
+}
+
+/// Metadata is only available since prometheus v2.15, catalogs of older versions have no types.
+fn or_no_metadata(
+    metadata: Result<BTreeMap<String, Vec<MetricMetadata>>, CliError>,
+) -> BTreeMap<String, Vec<MetricMetadata>> {
+    metadata.unwrap_or_else(|err| {
+        warn!("could not get metric metadata: {:?}", err);
+        BTreeMap::new()
+    })
+}
+
+fn to_types(metadata: Option<&Vec<MetricMetadata>>) -> BTreeSet<String> {
+    metadata
+        .into_iter()
+        .flat_map(|metadata| metadata.iter())
+        .map(|metadata| metadata.metric_type.clone())
+        .collect()
+}
+
+impl MetricCatalog for PrometheusCatalog {
+    fn metric_names(&self) -> BTreeSet<String> {
+        self.names.clone()
+    }
+
+    fn has_metric(&self, metric: &str) -> bool {
+        self.names.contains(metric)
+    }
+
+    fn get_labels(&self, metric: &str) -> Option<BTreeSet<String>> {
+        self.label_sets
+            .as_ref()
+            .and_then(|label_sets| label_sets.get(metric))
+            .cloned()
+    }
+
+    fn get_types(&self, metric: &str) -> BTreeSet<String> {
+        to_types(self.metadata.get(metric))
+    }
+
+    /// Every metric of a live prometheus is seen when the catalog is loaded.
+    fn last_seen(&self, metric: &str) -> Option<u64> {
+        if self.has_metric(metric) {
+            Some(self.loaded_at)
+        } else {
+            None
+        }
+    }
+
+    /// The series are not loaded, export a snapshot to know the jobs.
+    fn get_jobs(&self, _metric: &str) -> BTreeSet<String> {
+        BTreeSet::new()
+    }
+}
+
+impl MetricCatalog for CatalogSnapshot {
+    fn metric_names(&self) -> BTreeSet<String> {
+        self.metrics.keys().cloned().collect()
+    }
+
+    fn has_metric(&self, metric: &str) -> bool {
+        self.metrics.contains_key(metric)
+    }
+
+    fn get_labels(&self, metric: &str) -> Option<BTreeSet<String>> {
+        self.metrics
+            .get(metric)
+            .map(|metric| metric.labels.keys().cloned().collect())
+    }
+
+    fn get_types(&self, metric: &str) -> BTreeSet<String> {
+        to_types(self.metrics.get(metric).map(|metric| &metric.metadata))
+    }
+
+    fn last_seen(&self, metric: &str) -> Option<u64> {
+        if self.has_metric(metric) {
+            Some(self.created_at)
+        } else {
+            None
+        }
+    }
+
+    /// The sampled values of `job`, empty for metrics without, e.g. of /metrics dumps.
+    fn get_jobs(&self, metric: &str) -> BTreeSet<String> {
+        self.metrics
+            .get(metric)
+            .and_then(|metric| metric.labels.get("job"))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// Several catalogs as one, e.g. of every prometheus of a federation.
+pub struct UnionCatalog {
+    catalogs: Vec<Box<MetricCatalog>>,
+}
+
+impl UnionCatalog {
+    pub fn new(catalogs: Vec<Box<MetricCatalog>>) -> UnionCatalog {
+        UnionCatalog { catalogs }
+    }
+}
+
+impl MetricCatalog for UnionCatalog {
+    fn metric_names(&self) -> BTreeSet<String> {
+        self.catalogs
+            .iter()
+            .flat_map(|catalog| catalog.metric_names())
+            .collect()
+    }
+
+    fn has_metric(&self, metric: &str) -> bool {
+        self.catalogs.iter().any(|catalog| catalog.has_metric(metric))
+    }
+
+    fn get_labels(&self, metric: &str) -> Option<BTreeSet<String>> {
+        self.catalogs
+            .iter()
+            .filter_map(|catalog| catalog.get_labels(metric))
+            .fold(None, |labels, catalog_labels| {
+                let mut labels: BTreeSet<String> = labels.unwrap_or_default();
+                labels.extend(catalog_labels);
+                Some(labels)
+            })
+    }
+
+    fn get_types(&self, metric: &str) -> BTreeSet<String> {
+        self.catalogs
+            .iter()
+            .flat_map(|catalog| catalog.get_types(metric))
+            .collect()
+    }
+
+    fn last_seen(&self, metric: &str) -> Option<u64> {
+        self.catalogs
+            .iter()
+            .filter_map(|catalog| catalog.last_seen(metric))
+            .max()
+    }
+
+    fn get_jobs(&self, metric: &str) -> BTreeSet<String> {
+        self.catalogs
+            .iter()
+            .flat_map(|catalog| catalog.get_jobs(metric))
+            .collect()
+    }
+}
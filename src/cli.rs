@@ -1,32 +1,202 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
 use clap::{App as ClapApp, Arg};
 
+use api_client::ClientOptions;
+use cache::{Cache, CacheMode, CachedPrometheusClient, DEFAULT_TTL_SECS};
+use catalog::{MetricCatalog, PrometheusCatalog, UnionCatalog};
 use errors::*;
+use exposition::load_exposition;
 use grafana::GrafanaClient;
+use http_client::MIN_TIMEOUT_SECS;
+use kubernetes::{ManifestFileSource, DEFAULT_DASHBOARD_LABEL};
+use policy::parse_duration;
 use prometheus::PrometheusClient;
+use prometheus_config::*;
+use retry::MIN_REQUESTS_PER_SECOND;
+use snapshot::load_snapshot;
+use sources::*;
+use tls::TlsConfig;
 
-pub fn add_prometheus_args<'a, 'b>(cmd: ClapApp<'a, 'b>) -> ClapApp<'a, 'b> {
+/// Argument names of the connection options for one endpoint.
+pub struct EndpointArgs {
+    pub ca_cert: &'static str,
+    pub client_cert: &'static str,
+    pub client_key: &'static str,
+    pub server_name: &'static str,
+    pub insecure_skip_verify: &'static str,
+    pub timeout: &'static str,
+    pub retries: &'static str,
+    pub rate_limit: &'static str,
+}
+
+pub static PROMETHEUS_ARGS: EndpointArgs = EndpointArgs {
+    ca_cert: "prometheus-ca-cert",
+    client_cert: "prometheus-client-cert",
+    client_key: "prometheus-client-key",
+    server_name: "prometheus-server-name",
+    insecure_skip_verify: "prometheus-insecure-skip-verify",
+    timeout: "prometheus-timeout",
+    retries: "prometheus-retries",
+    rate_limit: "prometheus-rate-limit",
+};
+
+pub static GRAFANA_ARGS: EndpointArgs = EndpointArgs {
+    ca_cert: "grafana-ca-cert",
+    client_cert: "grafana-client-cert",
+    client_key: "grafana-client-key",
+    server_name: "grafana-server-name",
+    insecure_skip_verify: "grafana-insecure-skip-verify",
+    timeout: "grafana-timeout",
+    retries: "grafana-retries",
+    rate_limit: "grafana-rate-limit",
+};
+
+pub fn add_tls_args<'a, 'b>(cmd: ClapApp<'a, 'b>, names: &'static EndpointArgs) -> ClapApp<'a, 'b> {
+    cmd.arg(
+        Arg::with_name(names.ca_cert)
+            .long(names.ca_cert)
+            .value_name("FILE")
+            .help("PEM bundle with additional CA certificates")
+            .takes_value(true),
+    ).arg(
+        Arg::with_name(names.client_cert)
+            .long(names.client_cert)
+            .value_name("FILE")
+            .help("PEM client certificate")
+            .requires(names.client_key)
+            .takes_value(true),
+    ).arg(
+        Arg::with_name(names.client_key)
+            .long(names.client_key)
+            .value_name("FILE")
+            .help("PEM (PKCS #8) client key")
+            .requires(names.client_cert)
+            .takes_value(true),
+    ).arg(
+        Arg::with_name(names.server_name)
+            .long(names.server_name)
+            .value_name("NAME")
+            .help("server name used for SNI and certificate verification")
+            .takes_value(true),
+    ).arg(
+        Arg::with_name(names.insecure_skip_verify)
+            .long(names.insecure_skip_verify)
+            .help("do not verify the server certificate"),
+    )
+}
+
+pub fn get_tls_config(m: &clap::ArgMatches, names: &EndpointArgs) -> TlsConfig {
+    TlsConfig {
+        ca_cert: m.value_of(names.ca_cert).map(PathBuf::from),
+        client_cert: m.value_of(names.client_cert).map(PathBuf::from),
+        client_key: m.value_of(names.client_key).map(PathBuf::from),
+        server_name: m.value_of(names.server_name).map(|name| name.to_string()),
+        insecure_skip_verify: m.is_present(names.insecure_skip_verify),
+    }
+}
+
+pub fn add_request_args<'a, 'b>(
+    cmd: ClapApp<'a, 'b>,
+    names: &'static EndpointArgs,
+) -> ClapApp<'a, 'b> {
     cmd.arg(
+        Arg::with_name(names.timeout)
+            .long(names.timeout)
+            .value_name("SECONDS")
+            .help("request timeout in seconds (default: 30)")
+            .takes_value(true),
+    ).arg(
+        Arg::with_name(names.retries)
+            .long(names.retries)
+            .value_name("COUNT")
+            .help("retries for failed requests, 429 and 5xx responses (default: 3)")
+            .takes_value(true),
+    ).arg(
+        Arg::with_name(names.rate_limit)
+            .long(names.rate_limit)
+            .value_name("REQUESTS")
+            .help("maximum requests per second")
+            .takes_value(true),
+    )
+}
+
+pub fn get_client_options(
+    m: &clap::ArgMatches,
+    names: &EndpointArgs,
+) -> Result<ClientOptions, CliError> {
+    let mut options = ClientOptions::default();
+    options.tls = get_tls_config(m, names);
+
+    if let Some(timeout) = parse_bounded_arg(m, names.timeout, MIN_TIMEOUT_SECS)? {
+        options.timeout = Duration::from_millis((timeout * 1000.0) as u64);
+    }
+
+    if let Some(retries) = parse_arg::<u32>(m, names.retries)? {
+        options.retry.max_retries = retries;
+    }
+
+    options.requests_per_second = parse_bounded_arg(m, names.rate_limit, MIN_REQUESTS_PER_SECOND)?;
+
+    Ok(options)
+}
+
+/// Parse a finite number of at least `min`, NaN and infinity are rejected.
+fn parse_bounded_arg(m: &clap::ArgMatches, name: &str, min: f64) -> Result<Option<f64>, CliError> {
+    match parse_arg::<f64>(m, name)? {
+        Some(value) if !value.is_finite() || value < min => Err(CliError::InvalidArgument(
+            name.to_string(),
+            get_arg(m, name)?,
+        )),
+        value => Ok(value),
+    }
+}
+
+pub fn add_prometheus_args<'a, 'b>(cmd: ClapApp<'a, 'b>) -> ClapApp<'a, 'b> {
+    let cmd = cmd.arg(
         Arg::with_name("prometheus-url")
             .long("prometheus-url")
             .value_name("URL")
             .takes_value(true),
-    )
+    ).arg(
+        Arg::with_name("prometheus-series-window")
+            .long("prometheus-series-window")
+            .value_name("DURATION")
+            .help("only look up series of the last DURATION, e.g. 1h (default: 1h)")
+            .takes_value(true),
+    );
+
+    add_request_args(add_tls_args(cmd, &PROMETHEUS_ARGS), &PROMETHEUS_ARGS)
 }
 
 pub fn add_grafana_args<'a, 'b>(cmd: ClapApp<'a, 'b>) -> ClapApp<'a, 'b> {
-    cmd.arg(
+    add_grafana_args_required(cmd, true)
+}
+
+/// Grafana arguments for commands which only use grafana if a url is given.
+pub fn add_optional_grafana_args<'a, 'b>(cmd: ClapApp<'a, 'b>) -> ClapApp<'a, 'b> {
+    add_grafana_args_required(cmd, false)
+}
+
+fn add_grafana_args_required<'a, 'b>(cmd: ClapApp<'a, 'b>, required: bool) -> ClapApp<'a, 'b> {
+    let cmd = cmd.arg(
         Arg::with_name("grafana-url")
             .long("grafana-url")
             .value_name("URL")
-            .required(true)
+            .required(required)
+            .requires("grafana-api-key")
             .takes_value(true),
     ).arg(
         Arg::with_name("grafana-api-key")
             .long("grafana-api-key")
             .value_name("KEY")
-            .required(true)
+            .required(required)
             .takes_value(true),
-    )
+    );
+
+    add_request_args(add_tls_args(cmd, &GRAFANA_ARGS), &GRAFANA_ARGS)
 }
 
 pub fn get_arg(m: &clap::ArgMatches, name: &str) -> Result<String, CliError> {
@@ -35,21 +205,377 @@ pub fn get_arg(m: &clap::ArgMatches, name: &str) -> Result<String, CliError> {
         .ok_or(CliError::ArgumentRequired(name.to_string()))
 }
 
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+pub fn add_concurrency_arg<'a, 'b>(cmd: ClapApp<'a, 'b>) -> ClapApp<'a, 'b> {
+    cmd.arg(
+        Arg::with_name("concurrency")
+            .long("concurrency")
+            .value_name("COUNT")
+            .help("maximum requests in flight per endpoint (default: 8)")
+            .takes_value(true),
+    )
+}
+
+pub fn get_concurrency(m: &clap::ArgMatches) -> Result<usize, CliError> {
+    match parse_arg::<usize>(m, "concurrency")? {
+        Some(0) => Err(CliError::InvalidArgument(
+            "concurrency".to_string(),
+            "0".to_string(),
+        )),
+        Some(concurrency) => Ok(concurrency),
+        None => Ok(DEFAULT_CONCURRENCY),
+    }
+}
+
+/// Parse an optional argument, a present but malformed value is an error.
+pub fn parse_arg<T: FromStr>(m: &clap::ArgMatches, name: &str) -> Result<Option<T>, CliError> {
+    match m.value_of(name) {
+        Some(value) => value
+            .parse::<T>()
+            .map(Some)
+            .map_err(|_| CliError::InvalidArgument(name.to_string(), value.to_string())),
+        None => Ok(None),
+    }
+}
+
 pub fn get_grafana_client(m: &clap::ArgMatches) -> Result<GrafanaClient, CliError> {
+    let options = get_client_options(m, &GRAFANA_ARGS)?;
+
     get_arg(m, "grafana-url").and_then(|url| {
         GrafanaClient::new(
             url.to_string(),
             m.value_of("grafana-api-key")
                 .map(|api_key| api_key.to_string()),
+            &options,
         ).map_err(CliError::ClientError)
     })
 }
 pub fn get_prometheus_client(m: &clap::ArgMatches) -> Result<PrometheusClient, CliError> {
-    get_arg(m, "prometheus-url")
-        .and_then(|url| PrometheusClient::new(url.to_string()).map_err(CliError::ClientError))
+    let options = get_client_options(m, &PROMETHEUS_ARGS)?;
+
+    let client = get_arg(m, "prometheus-url").and_then(|url| {
+        PrometheusClient::new(url.to_string(), &options).map_err(CliError::ClientError)
+    })?;
+
+    match get_series_window(m)? {
+        Some(series_window) => Ok(client.with_series_window(series_window)),
+        None => Ok(client),
+    }
+}
+
+pub fn get_series_window(m: &clap::ArgMatches) -> Result<Option<Duration>, CliError> {
+    let name = "prometheus-series-window";
+
+    match m.value_of(name) {
+        Some(value) => match parse_duration(value) {
+            Some(seconds) if seconds >= 1.0 => Ok(Some(Duration::from_secs(seconds as u64))),
+            _ => Err(CliError::InvalidArgument(name.to_string(), value.to_string())),
+        },
+        None => Ok(None),
+    }
+}
+
+pub fn add_cache_args<'a, 'b>(cmd: ClapApp<'a, 'b>) -> ClapApp<'a, 'b> {
+    cmd.arg(
+        Arg::with_name("cache-dir")
+            .long("cache-dir")
+            .value_name("DIR")
+            .help("directory of the metric catalog cache (default: ~/.cache/promqueen)")
+            .takes_value(true),
+    ).arg(
+        Arg::with_name("cache-ttl")
+            .long("cache-ttl")
+            .value_name("SECONDS")
+            .help("maximum age of cached metric catalogs (default: 3600)")
+            .takes_value(true),
+    ).arg(
+        Arg::with_name("refresh")
+            .long("refresh")
+            .help("ignore the cache and fetch the metric catalog again")
+            .conflicts_with("offline"),
+    ).arg(
+        Arg::with_name("offline")
+            .long("offline")
+            .help("only use the cached metric catalog, regardless of its age"),
+    )
+}
+
+pub fn get_cache(m: &clap::ArgMatches) -> Result<Cache, CliError> {
+    let dir = m
+        .value_of("cache-dir")
+        .map(PathBuf::from)
+        .or_else(Cache::default_dir)
+        .ok_or_else(|| CliError::ArgumentRequired("cache-dir".to_string()))?;
+    let ttl = parse_arg::<u64>(m, "cache-ttl")?.unwrap_or(DEFAULT_TTL_SECS);
+    let mode = if m.is_present("offline") {
+        CacheMode::Offline
+    } else if m.is_present("refresh") {
+        CacheMode::Refresh
+    } else {
+        CacheMode::ReadWrite
+    };
+
+    Ok(Cache::new(dir, Duration::from_secs(ttl), mode))
+}
+
+pub fn get_cached_prometheus_client(
+    m: &clap::ArgMatches,
+) -> Result<CachedPrometheusClient, CliError> {
+    let url = get_arg(m, "prometheus-url")?;
+
+    Ok(CachedPrometheusClient::new(
+        url,
+        get_prometheus_client(m)?,
+        get_cache(m)?,
+    ))
+}
+
+pub fn add_snapshot_arg<'a, 'b>(cmd: ClapApp<'a, 'b>) -> ClapApp<'a, 'b> {
+    cmd.arg(
+        Arg::with_name("snapshot")
+            .long("snapshot")
+            .value_name("FILE")
+            .help("use a metric catalog exported by export-catalog instead of the one of --prometheus-url")
+            .takes_value(true),
+    )
+}
+
+/// Label values from the `--snapshot` file if given, otherwise from the cached prometheus catalog.
+pub fn get_catalog_label_values(m: &clap::ArgMatches, label: String) -> Result<Vec<String>, CliError> {
+    match m.value_of("snapshot") {
+        Some(path) => Ok(load_snapshot(&PathBuf::from(path))?.get_label_values(&label)),
+        None => get_cached_prometheus_client(m)?.get_label_values(label),
+    }
+}
+
+/// Catalog arguments of validators, several catalogs are validated against as one.
+pub fn add_catalog_args<'a, 'b>(cmd: ClapApp<'a, 'b>) -> ClapApp<'a, 'b> {
+    add_file_catalog_args(cmd).arg(
+        Arg::with_name("catalog-url")
+            .long("catalog-url")
+            .value_name("URL")
+            .help("use the metric catalog of this prometheus instead of the one of --prometheus-url")
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+    )
+}
+
+/// The `--snapshot` and `--exposition-file` arguments of `add_catalog_args`, for commands which
+/// need the labels and jobs a live prometheus catalog does not have.
+pub fn add_file_catalog_args<'a, 'b>(cmd: ClapApp<'a, 'b>) -> ClapApp<'a, 'b> {
+    cmd.arg(
+        Arg::with_name("snapshot")
+            .long("snapshot")
+            .value_name("FILE")
+            .help("use a metric catalog exported by export-catalog instead of the one of --prometheus-url")
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+    ).arg(
+        Arg::with_name("exposition-file")
+            .long("exposition-file")
+            .value_name("FILE")
+            .help("use the metrics of a /metrics dump, text format or OpenMetrics, - reads stdin")
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+    )
+}
+
+/// The union of all `--snapshot`, `--exposition-file` and `--catalog-url` catalogs,
+/// the `--prometheus-url` catalog if none is given.
+pub fn get_catalog(m: &clap::ArgMatches) -> Result<Box<MetricCatalog>, CliError> {
+    get_catalog_with_cache(m, get_cache(m)?)
+}
+
+/// Like `get_catalog`, with the prometheus catalogs read through `cache`, e.g. to reload them
+/// periodically with `CacheMode::Refresh`.
+pub fn get_catalog_with_cache(
+    m: &clap::ArgMatches,
+    cache: Cache,
+) -> Result<Box<MetricCatalog>, CliError> {
+    let mut catalogs: Vec<Box<MetricCatalog>> = get_file_catalogs(m)?
+        .into_iter()
+        .map(|(_, catalog)| catalog)
+        .collect();
+
+    for url in m.values_of("catalog-url").into_iter().flatten() {
+        let options = get_client_options(m, &PROMETHEUS_ARGS)?;
+        let client = PrometheusClient::new(url.to_string(), &options).map_err(CliError::ClientError)?;
+        let client = CachedPrometheusClient::new(url.to_string(), client, cache.clone());
+
+        catalogs.push(Box::new(PrometheusCatalog::load_cached(&client)?));
+    }
+
+    if catalogs.is_empty() {
+        let url = get_arg(m, "prometheus-url")?;
+        let client = CachedPrometheusClient::new(url, get_prometheus_client(m)?, cache);
+
+        return Ok(Box::new(PrometheusCatalog::load_cached(&client)?));
+    }
+
+    if catalogs.len() == 1 {
+        return Ok(catalogs.remove(0));
+    }
+
+    Ok(Box::new(UnionCatalog::new(catalogs)))
+}
+
+/// A catalog with the path it was read from.
+pub type NamedCatalog = (String, Box<MetricCatalog>);
+
+/// Every `--snapshot` and `--exposition-file` catalog, with its path.
+pub fn get_file_catalogs(m: &clap::ArgMatches) -> Result<Vec<NamedCatalog>, CliError> {
+    let mut catalogs: Vec<NamedCatalog> = vec![];
+
+    for path in m.values_of("snapshot").into_iter().flatten() {
+        catalogs.push((
+            path.to_string(),
+            Box::new(load_snapshot(&PathBuf::from(path))?),
+        ));
+    }
+
+    for path in m.values_of("exposition-file").into_iter().flatten() {
+        catalogs.push((
+            path.to_string(),
+            Box::new(load_exposition(&PathBuf::from(path))?),
+        ));
+    }
+
+    Ok(catalogs)
+}
+
+/// Expression source arguments, grafana is used as source if `--grafana-url` is given.
+pub fn add_source_args<'a, 'b>(cmd: ClapApp<'a, 'b>) -> ClapApp<'a, 'b> {
+    cmd.arg(
+        Arg::with_name("dashboard-file")
+            .long("dashboard-file")
+            .value_name("FILE")
+            .help("dashboard json file to read expressions from")
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+    ).arg(
+        Arg::with_name("rule-file")
+            .long("rule-file")
+            .value_name("FILE")
+            .help("prometheus rule file to read expressions from")
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+    ).arg(
+        Arg::with_name("prometheus-config")
+            .long("prometheus-config")
+            .value_name("FILE")
+            .help("prometheus.yml to read the rule_files and scrape job names from")
+            .takes_value(true),
+    ).arg(
+        Arg::with_name("manifest-file")
+            .long("manifest-file")
+            .value_name("FILE")
+            .help("kubernetes manifests with PrometheusRules and dashboard ConfigMaps, - reads stdin")
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+    ).arg(
+        Arg::with_name("dashboard-label")
+            .long("dashboard-label")
+            .value_name("LABEL")
+            .help("label of dashboard ConfigMaps in --manifest-file")
+            .default_value(DEFAULT_DASHBOARD_LABEL),
+    ).arg(
+        Arg::with_name("prometheus-rules")
+            .long("prometheus-rules")
+            .help("read the rules loaded by --prometheus-url from its rules api")
+            .requires("prometheus-url"),
+    )
+}
+
+pub fn get_sources(m: &clap::ArgMatches) -> Result<Vec<Box<ExpressionSource>>, CliError> {
+    let mut sources: Vec<Box<ExpressionSource>> = vec![];
+
+    if m.is_present("grafana-url") {
+        sources.push(Box::new(GrafanaSource::new(
+            get_arg(m, "grafana-url")?,
+            get_grafana_client(m)?,
+            get_concurrency(m)?,
+        )));
+    }
+
+    for path in m.values_of("dashboard-file").into_iter().flatten() {
+        sources.push(Box::new(DashboardFileSource::new(PathBuf::from(path))));
+    }
+
+    for path in m.values_of("rule-file").into_iter().flatten() {
+        sources.push(Box::new(RuleFileSource::new(PathBuf::from(path))));
+    }
+
+    if let Some((path, config)) = get_prometheus_config(m)? {
+        for rule_file in get_rule_file_paths(&path, &config)? {
+            sources.push(Box::new(RuleFileSource::new(rule_file)));
+        }
+    }
+
+    for path in m.values_of("manifest-file").into_iter().flatten() {
+        sources.push(Box::new(ManifestFileSource::new(
+            PathBuf::from(path),
+            get_arg(m, "dashboard-label")?,
+        )));
+    }
+
+    if m.is_present("prometheus-rules") {
+        sources.push(Box::new(PrometheusRulesSource::new(
+            get_arg(m, "prometheus-url")?,
+            get_prometheus_client(m)?,
+        )));
+    }
+
+    Ok(sources)
+}
+
+/// The `--prometheus-config` with its path, if given.
+pub fn get_prometheus_config(
+    m: &clap::ArgMatches,
+) -> Result<Option<(PathBuf, PrometheusConfig)>, CliError> {
+    match m.value_of("prometheus-config") {
+        Some(path) => {
+            let path = PathBuf::from(path);
+            let config = load_prometheus_config(&path)?;
+
+            Ok(Some((path, config)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Like `get_sources`, but at least one source is required.
+pub fn get_required_sources(
+    m: &clap::ArgMatches,
+) -> Result<Vec<Box<ExpressionSource>>, CliError> {
+    let sources = get_sources(m)?;
+
+    if sources.is_empty() {
+        return Err(CliError::ArgumentRequired(
+            "grafana-url, dashboard-file, rule-file, prometheus-config, manifest-file or prometheus-rules"
+                .to_string(),
+        ));
+    }
+
+    Ok(sources)
+}
+
+/// What a command prints to stdout.
+#[derive(Debug, Clone)]
+pub enum CommandOutput {
+    /// Printed as pretty json, e.g. reports.
+    Json(serde_json::Value),
+    /// Printed as is, e.g. the dot or mermaid graph of export-graph.
+    Text(String),
 }
 
-pub type CommandResult = Result<serde_json::Value, CliError>;
+pub type CommandResult = Result<CommandOutput, CliError>;
 
 pub trait Command {
     fn get_name<'a>(&self) -> &'a str;
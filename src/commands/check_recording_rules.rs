@@ -0,0 +1,55 @@
+use clap::SubCommand;
+
+use super::super::cli::*;
+use super::super::errors::*;
+use super::super::prometheus_config::DEFAULT_EVALUATION_INTERVAL_SECS;
+use super::super::recording_rules::check_recording_rules;
+use super::super::report::*;
+use super::super::sources::get_expressions_from_sources;
+
+pub struct CheckRecordingRulesCmd {}
+
+impl Command for CheckRecordingRulesCmd {
+    fn get_name<'a>(&self) -> &'a str {
+        "check-recording-rules"
+    }
+
+    fn get_cmd<'a>(&self) -> clap::App<'a, 'a> {
+        let mut cmd = SubCommand::with_name(self.get_name())
+            .about("check naming, order, cycles, usage and inputs of recording rules");
+
+        cmd = add_cache_args(add_prometheus_args(add_optional_grafana_args(cmd)));
+        cmd = add_catalog_args(add_concurrency_arg(add_source_args(cmd)));
+
+        cmd
+    }
+
+    fn run_cmd(&self, matches: &clap::ArgMatches) -> CommandResult {
+        let mut report = ReportBuilder::new(self.get_name());
+        let sources = get_required_sources(matches)?;
+
+        let catalog = get_catalog(matches)?;
+        let default_interval = match get_prometheus_config(matches)? {
+            Some((path, config)) => config
+                .evaluation_interval()
+                .map_err(|message| CliError::PrometheusConfigError(path, message))?,
+            None => DEFAULT_EVALUATION_INTERVAL_SECS,
+        };
+
+        let expressions = get_expressions_from_sources(&sources)?;
+
+        for source in sources.iter() {
+            report.add_source(&source.name());
+        }
+        report.add_expressions(&expressions);
+        report.add_findings(check_recording_rules(
+            &expressions,
+            catalog.as_ref(),
+            default_interval,
+        ));
+
+        serde_json::to_value(report.finish())
+            .map(CommandOutput::Json)
+            .map_err(CliError::SerdeError)
+    }
+}
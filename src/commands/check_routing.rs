@@ -0,0 +1,52 @@
+use std::path::PathBuf;
+
+use clap::{Arg, SubCommand};
+
+use super::super::alertmanager::*;
+use super::super::cli::*;
+use super::super::errors::*;
+use super::super::report::*;
+use super::super::sources::get_expressions_from_sources;
+
+pub struct CheckRoutingCmd {}
+
+impl Command for CheckRoutingCmd {
+    fn get_name<'a>(&self) -> &'a str {
+        "check-routing"
+    }
+
+    fn get_cmd<'a>(&self) -> clap::App<'a, 'a> {
+        let mut cmd = SubCommand::with_name(self.get_name())
+            .about("route the alerting rules of the given sources through an alertmanager config");
+
+        cmd = add_source_args(add_prometheus_args(add_optional_grafana_args(cmd)));
+        cmd = add_concurrency_arg(cmd).arg(
+            Arg::with_name("alertmanager-config")
+                .long("alertmanager-config")
+                .value_name("FILE")
+                .help("alertmanager.yml with the routing tree and inhibit rules")
+                .required(true)
+                .takes_value(true),
+        );
+
+        cmd
+    }
+
+    fn run_cmd(&self, matches: &clap::ArgMatches) -> CommandResult {
+        let mut report = ReportBuilder::new(self.get_name());
+        let alertmanager =
+            load_alertmanager_config(&PathBuf::from(get_arg(matches, "alertmanager-config")?))?;
+        let sources = get_required_sources(matches)?;
+        let expressions = get_expressions_from_sources(&sources)?;
+
+        for source in sources.iter() {
+            report.add_source(&source.name());
+        }
+        report.add_expressions(&expressions);
+        report.add_findings(check_routing(&expressions, &alertmanager));
+
+        serde_json::to_value(report.finish())
+            .map(CommandOutput::Json)
+            .map_err(CliError::SerdeError)
+    }
+}
@@ -0,0 +1,78 @@
+use std::path::Path;
+
+use clap::{Arg, SubCommand};
+
+use super::super::cli::*;
+use super::super::diff::*;
+use super::super::errors::*;
+use super::super::prometheus::PrometheusClient;
+use super::super::snapshot::*;
+use super::super::sources::get_expressions_from_sources;
+use super::super::usage::*;
+
+pub struct DiffCatalogsCmd {}
+
+impl Command for DiffCatalogsCmd {
+    fn get_name<'a>(&self) -> &'a str {
+        "diff-catalogs"
+    }
+
+    fn get_cmd<'a>(&self) -> clap::App<'a, 'a> {
+        let mut cmd = SubCommand::with_name(self.get_name())
+            .about("list added, removed and label-changed metrics between two catalogs");
+
+        cmd = add_source_args(add_prometheus_args(add_optional_grafana_args(cmd)));
+        cmd = add_concurrency_arg(cmd)
+            .arg(
+                Arg::with_name("old")
+                    .help("prometheus url or snapshot file of the old catalog")
+                    .required(true)
+                    .index(1),
+            ).arg(
+                Arg::with_name("new")
+                    .help("prometheus url or snapshot file of the new catalog")
+                    .required(true)
+                    .index(2),
+            );
+
+        cmd
+    }
+
+    fn run_cmd(&self, matches: &clap::ArgMatches) -> CommandResult {
+        let concurrency = get_concurrency(matches)?;
+        let old = get_catalog_from_source(matches, &get_arg(matches, "old")?, concurrency)?;
+        let new = get_catalog_from_source(matches, &get_arg(matches, "new")?, concurrency)?;
+
+        let mut diff = diff_catalogs(&old, &new);
+
+        let expressions = get_expressions_from_sources(&get_sources(matches)?)?;
+        let usages = get_used_metrics_from_expressions(&expressions);
+
+        add_removed_usages(&mut diff, &usages);
+
+        serde_json::to_value(diff)
+            .map(CommandOutput::Json)
+            .map_err(CliError::SerdeError)
+    }
+}
+
+/// Export the catalog of a prometheus url, or load a snapshot file.
+fn get_catalog_from_source(
+    m: &clap::ArgMatches,
+    source: &str,
+    concurrency: usize,
+) -> Result<CatalogSnapshot, CliError> {
+    if !source.starts_with("http://") && !source.starts_with("https://") {
+        return load_snapshot(Path::new(source));
+    }
+
+    let options = get_client_options(m, &PROMETHEUS_ARGS)?;
+    let mut client =
+        PrometheusClient::new(source.to_string(), &options).map_err(CliError::ClientError)?;
+
+    if let Some(series_window) = get_series_window(m)? {
+        client = client.with_series_window(series_window);
+    }
+
+    export_snapshot(source, &client, 0, concurrency)
+}
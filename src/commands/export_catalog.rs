@@ -0,0 +1,69 @@
+use std::path::Path;
+
+use clap::{Arg, SubCommand};
+
+use super::super::cli::*;
+use super::super::errors::*;
+use super::super::snapshot::*;
+
+pub struct ExportCatalogCmd {}
+
+impl Command for ExportCatalogCmd {
+    fn get_name<'a>(&self) -> &'a str {
+        "export-catalog"
+    }
+
+    fn get_cmd<'a>(&self) -> clap::App<'a, 'a> {
+        let mut cmd = SubCommand::with_name(self.get_name())
+            .about("export the metric catalog of prometheus to a snapshot file");
+
+        cmd = add_concurrency_arg(add_prometheus_args(cmd))
+            .arg(
+                Arg::with_name("output")
+                    .long("output")
+                    .short("o")
+                    .value_name("FILE")
+                    .help("write the snapshot to FILE instead of stdout")
+                    .takes_value(true),
+            ).arg(
+                Arg::with_name("sample-values")
+                    .long("sample-values")
+                    .value_name("COUNT")
+                    .help("maximum sampled values per label")
+                    .default_value("10"),
+            );
+
+        cmd
+    }
+
+    fn run_cmd(&self, matches: &clap::ArgMatches) -> CommandResult {
+        let prometheus_client = get_prometheus_client(matches)?;
+        let url = get_arg(matches, "prometheus-url")?;
+        let concurrency = get_concurrency(matches)?;
+        let sample_values = parse_arg::<usize>(matches, "sample-values")?.unwrap_or(10);
+
+        let snapshot = export_snapshot(&url, &prometheus_client, sample_values, concurrency)?;
+
+        match matches.value_of("output") {
+            Some(output) => {
+                save_snapshot(Path::new(output), &snapshot)?;
+
+                Ok(CommandOutput::Json(json_summary(output, &snapshot)))
+            }
+            None => serde_json::to_value(snapshot)
+                .map(CommandOutput::Json)
+                .map_err(CliError::SerdeError),
+        }
+    }
+}
+
+fn json_summary(output: &str, snapshot: &CatalogSnapshot) -> serde_json::Value {
+    let mut summary = serde_json::Map::new();
+    summary.insert("file".to_string(), serde_json::Value::from(output));
+    summary.insert(
+        "metrics".to_string(),
+        serde_json::Value::from(snapshot.metrics.len()),
+    );
+
+    serde_json::Value::Object(summary)
+}
@@ -0,0 +1,111 @@
+use std::fs;
+use std::path::Path;
+
+use clap::{Arg, SubCommand};
+
+use super::super::cli::*;
+use super::super::dependency_graph::*;
+use super::super::errors::*;
+use super::super::recording_rules::{get_definitions, get_recording_rules};
+use super::super::sources::get_expressions_from_sources;
+
+pub struct ExportGraphCmd {}
+
+impl Command for ExportGraphCmd {
+    fn get_name<'a>(&self) -> &'a str {
+        "export-graph"
+    }
+
+    fn get_cmd<'a>(&self) -> clap::App<'a, 'a> {
+        let mut cmd = SubCommand::with_name(self.get_name())
+            .about("export the dependencies of panels and alerts on recording rules and metrics");
+
+        cmd = add_source_args(add_prometheus_args(add_optional_grafana_args(cmd)));
+        cmd = add_concurrency_arg(cmd)
+            .arg(
+                Arg::with_name("format")
+                    .long("format")
+                    .value_name("FORMAT")
+                    .help("dot, mermaid or json")
+                    .possible_values(&["dot", "mermaid", "json"])
+                    .default_value("dot"),
+            ).arg(
+                Arg::with_name("dashboard")
+                    .long("dashboard")
+                    .value_name("UID")
+                    .help("only the panels of the dashboard and their dependencies, its title for dashboards without uid")
+                    .takes_value(true),
+            ).arg(
+                Arg::with_name("metric")
+                    .long("metric")
+                    .value_name("METRIC")
+                    .help("only the metric, what depends on it and what it is recorded from")
+                    .conflicts_with("dashboard")
+                    .takes_value(true),
+            ).arg(
+                Arg::with_name("output")
+                    .long("output")
+                    .short("o")
+                    .value_name("FILE")
+                    .help("write the graph to FILE instead of stdout")
+                    .takes_value(true),
+            );
+
+        cmd
+    }
+
+    fn run_cmd(&self, matches: &clap::ArgMatches) -> CommandResult {
+        let sources = get_required_sources(matches)?;
+        let expressions = get_expressions_from_sources(&sources)?;
+
+        let rules = get_recording_rules(&expressions);
+        let definitions = get_definitions(&rules);
+
+        let mut graph = DependencyGraph::new(&expressions, &definitions);
+        if let Some(dashboard) = matches.value_of("dashboard") {
+            graph = graph.for_dashboard(dashboard);
+        }
+        if let Some(metric) = matches.value_of("metric") {
+            graph = graph.for_metric(metric);
+        }
+
+        let output = match get_arg(matches, "format")?.as_ref() {
+            "json" => {
+                CommandOutput::Json(serde_json::to_value(&graph).map_err(CliError::SerdeError)?)
+            }
+            "mermaid" => CommandOutput::Text(graph.to_mermaid()),
+            _ => CommandOutput::Text(graph.to_dot()),
+        };
+
+        match matches.value_of("output") {
+            Some(path) => {
+                let content = match output {
+                    CommandOutput::Json(value) => {
+                        serde_json::to_string_pretty(&value).map_err(CliError::SerdeError)?
+                    }
+                    CommandOutput::Text(text) => text,
+                };
+                fs::write(path, content)
+                    .map_err(|err| CliError::IoError(Path::new(path).to_path_buf(), err))?;
+
+                Ok(CommandOutput::Json(json_summary(path, &graph)))
+            }
+            None => Ok(output),
+        }
+    }
+}
+
+fn json_summary(output: &str, graph: &DependencyGraph) -> serde_json::Value {
+    let mut summary = serde_json::Map::new();
+    summary.insert("file".to_string(), serde_json::Value::from(output));
+    summary.insert(
+        "nodes".to_string(),
+        serde_json::Value::from(graph.nodes.len()),
+    );
+    summary.insert(
+        "edges".to_string(),
+        serde_json::Value::from(graph.edges.len()),
+    );
+
+    serde_json::Value::Object(summary)
+}
@@ -21,7 +21,7 @@ impl Command for GetAlertsCmd {
         let values = prometheus_client
             .get_alerts()
             .map_err(CliError::ClientError)
-            .map(|result| serde_json::to_value(result).unwrap());
+            .map(|result| CommandOutput::Json(serde_json::to_value(result).unwrap()));
 
         values
     }
@@ -1,7 +1,6 @@
 use clap::{Arg, SubCommand};
 
 use super::super::cli::*;
-use super::super::errors::*;
 
 pub struct GetValuesCmd {}
 
@@ -12,7 +11,7 @@ impl Command for GetValuesCmd {
     fn get_cmd<'a>(&self) -> clap::App<'a, 'a> {
         let mut cmd = SubCommand::with_name(self.get_name()).about("get prometheus label values");
 
-        cmd = add_prometheus_args(cmd).arg(
+        cmd = add_snapshot_arg(add_cache_args(add_prometheus_args(cmd))).arg(
             Arg::with_name("label")
                 .help("the label name for which all values should be exported")
                 .required(true)
@@ -23,13 +22,10 @@ impl Command for GetValuesCmd {
     }
 
     fn run_cmd(&self, matches: &clap::ArgMatches) -> CommandResult {
-        let prometheus_client = get_prometheus_client(matches)?;
         let label = get_arg(matches, "label")?;
 
-        let values = prometheus_client
-            .get_label_values(label)
-            .map_err(CliError::ClientError)
-            .map(|result| serde_json::to_value(result).unwrap());
+        let values = get_catalog_label_values(matches, label)
+            .map(|result| CommandOutput::Json(serde_json::to_value(result).unwrap()));
 
         values
     }
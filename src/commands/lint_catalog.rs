@@ -0,0 +1,45 @@
+use clap::SubCommand;
+
+use super::super::cli::*;
+use super::super::errors::*;
+use super::super::naming::lint_catalog;
+use super::super::report::*;
+
+pub struct LintCatalogCmd {}
+
+impl Command for LintCatalogCmd {
+    fn get_name<'a>(&self) -> &'a str {
+        "lint-catalog"
+    }
+
+    fn get_cmd<'a>(&self) -> clap::App<'a, 'a> {
+        let mut cmd = SubCommand::with_name(self.get_name())
+            .about("check the metric and label names of the catalog against the naming conventions");
+
+        // a live prometheus catalog knows neither the labels nor the jobs of its metrics
+        cmd = add_file_catalog_args(cmd);
+
+        cmd
+    }
+
+    fn run_cmd(&self, matches: &clap::ArgMatches) -> CommandResult {
+        let mut report = ReportBuilder::new(self.get_name());
+        let catalogs = get_file_catalogs(matches)?;
+
+        if catalogs.is_empty() {
+            return Err(CliError::ArgumentRequired(
+                "snapshot or exposition-file".to_string(),
+            ));
+        }
+
+        // findings are sorted by source and job, which groups them by catalog and job
+        for (path, catalog) in catalogs.iter() {
+            report.add_source(path);
+            report.add_findings(lint_catalog(path, catalog.as_ref()));
+        }
+
+        serde_json::to_value(report.finish())
+            .map(CommandOutput::Json)
+            .map_err(CliError::SerdeError)
+    }
+}
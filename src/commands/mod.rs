@@ -1,7 +1,31 @@
 pub mod get_values;
 pub mod validate_dashboard;
+pub mod validate_dashboards;
 pub mod get_alerts;
+pub mod serve;
+pub mod serve_api;
+pub mod export_catalog;
+pub mod diff_catalogs;
+pub mod validate;
+pub mod report_schema;
+pub mod predict_relabel;
+pub mod check_routing;
+pub mod lint_catalog;
+pub mod check_recording_rules;
+pub mod export_graph;
 
 pub use self::get_values::GetValuesCmd;
 pub use self::validate_dashboard::ValidateDashboardCmd;
-pub use self::get_alerts::GetAlertsCmd;
\ No newline at end of file
+pub use self::validate_dashboards::ValidateDashboardsCmd;
+pub use self::get_alerts::GetAlertsCmd;
+pub use self::serve::ServeCmd;
+pub use self::serve_api::ServeApiCmd;
+pub use self::export_catalog::ExportCatalogCmd;
+pub use self::diff_catalogs::DiffCatalogsCmd;
+pub use self::validate::ValidateCmd;
+pub use self::report_schema::ReportSchemaCmd;
+pub use self::predict_relabel::PredictRelabelCmd;
+pub use self::check_routing::CheckRoutingCmd;
+pub use self::lint_catalog::LintCatalogCmd;
+pub use self::check_recording_rules::CheckRecordingRulesCmd;
+pub use self::export_graph::ExportGraphCmd;
\ No newline at end of file
@@ -0,0 +1,81 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+use clap::{Arg, SubCommand};
+
+use super::super::cli::*;
+use super::super::errors::*;
+use super::super::prometheus_config::load_prometheus_config;
+use super::super::relabel::*;
+use super::super::report::*;
+use super::super::sources::get_expressions_from_sources;
+use super::super::usage::*;
+
+pub struct PredictRelabelCmd {}
+
+impl Command for PredictRelabelCmd {
+    fn get_name<'a>(&self) -> &'a str {
+        "predict-relabel"
+    }
+
+    fn get_cmd<'a>(&self) -> clap::App<'a, 'a> {
+        let mut cmd = SubCommand::with_name(self.get_name()).about(
+            "report the expressions which would lose metrics or labels by the relabel rules of --prometheus-config",
+        );
+
+        cmd = add_source_args(add_prometheus_args(add_optional_grafana_args(cmd)));
+        cmd = add_concurrency_arg(cmd).arg(
+            Arg::with_name("old-prometheus-config")
+                .long("old-prometheus-config")
+                .value_name("FILE")
+                .help("only predict the relabel rules missing in this version of prometheus.yml")
+                .requires("prometheus-config")
+                .takes_value(true),
+        );
+
+        cmd
+    }
+
+    fn run_cmd(&self, matches: &clap::ArgMatches) -> CommandResult {
+        let mut report = ReportBuilder::new(self.get_name());
+        let (path, config) = get_prometheus_config(matches)?
+            .ok_or_else(|| CliError::ArgumentRequired("prometheus-config".to_string()))?;
+        let old_config = match matches.value_of("old-prometheus-config") {
+            Some(old_path) => Some(load_prometheus_config(&PathBuf::from(old_path))?),
+            None => None,
+        };
+        let relabelers = get_relabelers(&config, old_config.as_ref())
+            .map_err(|message| CliError::PrometheusConfigError(path, message))?;
+
+        let prometheus_client = get_prometheus_client(matches)?;
+        let concurrency = get_concurrency(matches)?;
+        let sources = get_required_sources(matches)?;
+        let expressions = get_expressions_from_sources(&sources)?;
+
+        let metrics: BTreeSet<String> = expressions
+            .iter()
+            .filter_map(|expression| get_used_metrics_from_expression(&expression.expression).ok())
+            .flatten()
+            .collect();
+        let matchers = metrics
+            .iter()
+            .map(|name| format!("{{__name__=\"{}\"}}", name))
+            .collect();
+        let series = prometheus_client
+            .get_series(matchers, concurrency)
+            .map_err(CliError::ClientError)?;
+        let series_by_metric: BTreeMap<_, _> = metrics.into_iter().zip(series).collect();
+
+        let prediction = predict(&series_by_metric, &relabelers);
+
+        for source in sources.iter() {
+            report.add_source(&source.name());
+        }
+        report.add_expressions(&expressions);
+        report.add_findings(find_relabel_breakage(&expressions, &prediction));
+
+        serde_json::to_value(report.finish())
+            .map(CommandOutput::Json)
+            .map_err(CliError::SerdeError)
+    }
+}
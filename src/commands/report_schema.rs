@@ -0,0 +1,24 @@
+use clap::SubCommand;
+
+use super::super::cli::*;
+use super::super::errors::*;
+use super::super::report::report_schema;
+
+pub struct ReportSchemaCmd {}
+
+impl Command for ReportSchemaCmd {
+    fn get_name<'a>(&self) -> &'a str {
+        "report-schema"
+    }
+
+    fn get_cmd<'a>(&self) -> clap::App<'a, 'a> {
+        SubCommand::with_name(self.get_name())
+            .about("print the json schema of the reports of the validate commands")
+    }
+
+    fn run_cmd(&self, _matches: &clap::ArgMatches) -> CommandResult {
+        serde_json::to_value(report_schema())
+            .map(CommandOutput::Json)
+            .map_err(CliError::SerdeError)
+    }
+}
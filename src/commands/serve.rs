@@ -0,0 +1,92 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use clap::{Arg, SubCommand};
+use hyper::{Method, StatusCode};
+
+use super::super::cache::CacheMode;
+use super::super::cli::*;
+use super::super::errors::*;
+use super::super::exporter::*;
+use super::super::server;
+
+pub struct ServeCmd {}
+
+impl Command for ServeCmd {
+    fn get_name<'a>(&self) -> &'a str {
+        "serve"
+    }
+
+    fn get_cmd<'a>(&self) -> clap::App<'a, 'a> {
+        let mut cmd = SubCommand::with_name(self.get_name())
+            .about("periodically validate the expressions of the given sources and export the results as metrics");
+
+        cmd = add_source_args(add_prometheus_args(add_optional_grafana_args(cmd)));
+        cmd = add_catalog_args(add_concurrency_arg(cmd))
+            .arg(
+                Arg::with_name("listen-address")
+                    .long("listen-address")
+                    .value_name("ADDRESS")
+                    .help("address to serve /metrics on")
+                    .default_value("0.0.0.0:9754"),
+            ).arg(
+                Arg::with_name("interval")
+                    .long("interval")
+                    .value_name("SECONDS")
+                    .help("seconds between validation runs")
+                    .default_value("300"),
+            );
+
+        cmd
+    }
+
+    fn run_cmd(&self, matches: &clap::ArgMatches) -> CommandResult {
+        let sources = get_required_sources(matches)?;
+        let cache = get_cache(matches)?.with_mode(CacheMode::Refresh);
+        let addr = parse_arg::<SocketAddr>(matches, "listen-address")?
+            .ok_or_else(|| CliError::ArgumentRequired("listen-address".to_string()))?;
+        let interval = parse_arg::<u64>(matches, "interval")?
+            .map(Duration::from_secs)
+            .ok_or_else(|| CliError::ArgumentRequired("interval".to_string()))?;
+
+        let metrics = Arc::new(RwLock::new(String::new()));
+        let server_metrics = metrics.clone();
+
+        server::spawn(addr, move |request| {
+            match (request.method(), request.uri().path()) {
+                (&Method::GET, "/metrics") => server::respond(
+                    StatusCode::OK,
+                    "text/plain; version=0.0.4",
+                    server_metrics.read().expect("metrics lock is poisoned").clone(),
+                ),
+                _ => server::not_found(),
+            }
+        })?;
+
+        let mut last_summary: Option<ValidationSummary> = None;
+
+        loop {
+            let started_at = Instant::now();
+            let result = get_catalog_with_cache(matches, cache.clone())
+                .and_then(|catalog| validate_all(&sources, catalog.as_ref()));
+            let success = result.is_ok();
+
+            match result {
+                Ok(summary) => last_summary = Some(summary),
+                Err(err) => error!("validation run failed: {:?}", err),
+            }
+
+            let rendered = render_metrics(
+                last_summary.as_ref(),
+                success,
+                started_at.elapsed(),
+                SystemTime::now(),
+            );
+            *metrics.write().expect("metrics lock is poisoned") = rendered;
+
+            thread::sleep(interval);
+        }
+    }
+}
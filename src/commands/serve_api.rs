@@ -0,0 +1,87 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use clap::{Arg, SubCommand};
+use futures::{Future, Stream};
+
+use super::super::api_server::*;
+use super::super::cache::CacheMode;
+use super::super::cli::*;
+use super::super::errors::*;
+use super::super::server;
+
+pub struct ServeApiCmd {}
+
+impl Command for ServeApiCmd {
+    fn get_name<'a>(&self) -> &'a str {
+        "serve-api"
+    }
+
+    fn get_cmd<'a>(&self) -> clap::App<'a, 'a> {
+        let mut cmd = SubCommand::with_name(self.get_name())
+            .about("serve a json api to validate expressions, dashboards and rule files");
+
+        cmd = add_source_args(add_prometheus_args(add_optional_grafana_args(cmd)));
+        cmd = add_catalog_args(add_concurrency_arg(cmd))
+            .arg(
+                Arg::with_name("listen-address")
+                    .long("listen-address")
+                    .value_name("ADDRESS")
+                    .help("address to serve the api on")
+                    .default_value("0.0.0.0:9755"),
+            ).arg(
+                Arg::with_name("refresh-interval")
+                    .long("refresh-interval")
+                    .value_name("SECONDS")
+                    .help("seconds between reloads of the metric catalog and the where-used index")
+                    .default_value("300"),
+            );
+
+        cmd
+    }
+
+    fn run_cmd(&self, matches: &clap::ArgMatches) -> CommandResult {
+        let sources = get_required_sources(matches)?;
+        let cache = get_cache(matches)?.with_mode(CacheMode::Refresh);
+        let addr = parse_arg::<SocketAddr>(matches, "listen-address")?
+            .ok_or_else(|| CliError::ArgumentRequired("listen-address".to_string()))?;
+        let interval = parse_arg::<u64>(matches, "refresh-interval")?
+            .map(Duration::from_secs)
+            .ok_or_else(|| CliError::ArgumentRequired("refresh-interval".to_string()))?;
+
+        let catalog = get_catalog_with_cache(matches, cache.clone())?;
+        let state = Arc::new(RwLock::new(load_state(&sources, catalog)?));
+        let server_state = state.clone();
+
+        server::spawn(addr, move |request| {
+            let state = server_state.clone();
+            let (parts, body) = request.into_parts();
+
+            Box::new(body.concat2().and_then(move |body| {
+                let (status, response) = handle(
+                    &state.read().expect("api state lock is poisoned"),
+                    &parts.method,
+                    parts.uri.path(),
+                    parts.uri.query(),
+                    &body,
+                );
+
+                server::respond(status, "application/json", response)
+            }))
+        })?;
+
+        loop {
+            thread::sleep(interval);
+
+            let new_state = get_catalog_with_cache(matches, cache.clone())
+                .and_then(|catalog| load_state(&sources, catalog));
+
+            match new_state {
+                Ok(new_state) => *state.write().expect("api state lock is poisoned") = new_state,
+                Err(err) => error!("could not refresh the metric catalog: {:?}", err),
+            }
+        }
+    }
+}
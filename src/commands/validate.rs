@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+
+use clap::{Arg, SubCommand};
+
+use super::super::cli::*;
+use super::super::errors::*;
+use super::super::policy::*;
+use super::super::prometheus_config::find_unknown_jobs;
+use super::super::report::*;
+use super::super::sources::get_expressions_from_sources;
+use super::super::templates::{check_annotation_templates, check_legend_formats};
+
+pub struct ValidateCmd {}
+
+impl Command for ValidateCmd {
+    fn get_name<'a>(&self) -> &'a str {
+        "validate"
+    }
+
+    fn get_cmd<'a>(&self) -> clap::App<'a, 'a> {
+        let mut cmd = SubCommand::with_name(self.get_name())
+            .about("validate the expressions of any combination of dashboards and rules");
+
+        cmd = add_cache_args(add_prometheus_args(add_optional_grafana_args(cmd)));
+        cmd = add_catalog_args(add_concurrency_arg(add_source_args(cmd))).arg(
+            Arg::with_name("alert-policy")
+                .long("alert-policy")
+                .value_name("FILE")
+                .help("check the alerting rules against the conventions of this yaml file")
+                .takes_value(true),
+        );
+
+        cmd
+    }
+
+    fn run_cmd(&self, matches: &clap::ArgMatches) -> CommandResult {
+        let mut report = ReportBuilder::new(self.get_name());
+        let sources = get_required_sources(matches)?;
+
+        let catalog = get_catalog(matches)?;
+
+        let expressions = get_expressions_from_sources(&sources)?;
+
+        for source in sources.iter() {
+            report.add_source(&source.name());
+        }
+        report.add_expressions(&expressions);
+        report.add_findings(validate_expressions(&expressions, catalog.as_ref()));
+        report.add_findings(check_legend_formats(&expressions));
+        report.add_findings(check_annotation_templates(&expressions));
+
+        if let Some(path) = matches.value_of("alert-policy") {
+            let policy = load_alert_policy(&PathBuf::from(path))?;
+            report.add_findings(check_alert_policy(&expressions, &policy));
+        }
+
+        if let Some((_, config)) = get_prometheus_config(matches)? {
+            report.add_findings(find_unknown_jobs(&expressions, &config.job_names()));
+        }
+
+        serde_json::to_value(report.finish())
+            .map(CommandOutput::Json)
+            .map_err(CliError::SerdeError)
+    }
+}
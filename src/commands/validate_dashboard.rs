@@ -1,11 +1,10 @@
-use std::collections::HashSet;
-use std::iter::FromIterator;
-
 use clap::{Arg, SubCommand};
 
 use super::super::cli::*;
 use super::super::errors::*;
-use super::super::usage::*;
+use super::super::report::*;
+use super::super::sources::get_grafana_expressions;
+use super::super::templates::check_legend_formats;
 
 pub struct ValidateDashboardCmd {}
 
@@ -17,7 +16,7 @@ impl Command for ValidateDashboardCmd {
     fn get_cmd<'a>(&self) -> clap::App<'a, 'a> {
         let mut cmd = SubCommand::with_name(self.get_name()).about("get dashboard");
 
-        cmd = add_prometheus_args(add_grafana_args(cmd)).arg(
+        cmd = add_catalog_args(add_cache_args(add_prometheus_args(add_grafana_args(cmd)))).arg(
             Arg::with_name("uri")
                 .help("the dashboard uri to validate")
                 .required(true)
@@ -28,39 +27,26 @@ impl Command for ValidateDashboardCmd {
     }
 
     fn run_cmd(&self, matches: &clap::ArgMatches) -> CommandResult {
-        let prometheus_client = get_prometheus_client(matches)?;
+        let mut report = ReportBuilder::new(self.get_name());
         let grafana_client = get_grafana_client(matches)?;
+        let grafana_url = get_arg(matches, "grafana-url")?;
         let uri = get_arg(matches, "uri")?;
 
-        let metrics = prometheus_client
-            .get_label_values("__name__".to_string())
-            .map_err(CliError::ClientError)?;
+        let catalog = get_catalog(matches)?;
 
         let dashboard = grafana_client
             .get_dashboard_by_uri(uri)
             .map_err(CliError::ClientError)?;
 
-        let valid_metrics: HashSet<String> = HashSet::from_iter(metrics.into_iter());
-
-        let usages = get_used_metrics_from_dashboard(dashboard);
-
-        let inv: Vec<MetricsUsage> = usages
-            .iter()
-            .map(|usage| {
-                let invalid_metrics: HashSet<_> = usage
-                    .metrics
-                    .difference(&valid_metrics)
-                    .map(|metric| metric.to_owned())
-                    .collect();
+        let expressions = get_grafana_expressions(&grafana_url, &[dashboard]);
 
-                MetricsUsage {
-                    pointer: usage.pointer.clone(),
-                    expression: usage.expression.clone(),
-                    metrics: invalid_metrics,
-                }
-            }).filter(|usage| usage.metrics.len() > 0)
-            .collect();
+        report.add_source(&grafana_url);
+        report.add_expressions(&expressions);
+        report.add_findings(validate_expressions(&expressions, catalog.as_ref()));
+        report.add_findings(check_legend_formats(&expressions));
 
-        serde_json::to_value(inv).map_err(CliError::SerdeError)
+        serde_json::to_value(report.finish())
+            .map(CommandOutput::Json)
+            .map_err(CliError::SerdeError)
     }
 }
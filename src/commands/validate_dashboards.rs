@@ -0,0 +1,56 @@
+use clap::{Arg, SubCommand};
+
+use super::super::cli::*;
+use super::super::errors::*;
+use super::super::report::*;
+use super::super::sources::get_grafana_expressions;
+use super::super::templates::check_legend_formats;
+
+pub struct ValidateDashboardsCmd {}
+
+impl Command for ValidateDashboardsCmd {
+    fn get_name<'a>(&self) -> &'a str {
+        "validate-dashboards"
+    }
+
+    fn get_cmd<'a>(&self) -> clap::App<'a, 'a> {
+        let mut cmd = SubCommand::with_name(self.get_name())
+            .about("validate many dashboards concurrently, all dashboards if no uri is given");
+
+        cmd = add_cache_args(add_prometheus_args(add_grafana_args(cmd)));
+        cmd = add_catalog_args(add_concurrency_arg(cmd)).arg(
+            Arg::with_name("uri")
+                .help("the dashboard uris to validate")
+                .multiple(true)
+                .index(1),
+        );
+
+        cmd
+    }
+
+    fn run_cmd(&self, matches: &clap::ArgMatches) -> CommandResult {
+        let mut report = ReportBuilder::new(self.get_name());
+        let grafana_client = get_grafana_client(matches)?;
+        let grafana_url = get_arg(matches, "grafana-url")?;
+        let concurrency = get_concurrency(matches)?;
+
+        let catalog = get_catalog(matches)?;
+
+        let dashboards = match matches.values_of("uri") {
+            Some(uris) => grafana_client
+                .get_dashboards(uris.map(|uri| uri.to_string()).collect(), concurrency),
+            None => grafana_client.get_all_dashboards(concurrency),
+        }.map_err(CliError::ClientError)?;
+
+        let expressions = get_grafana_expressions(&grafana_url, &dashboards);
+
+        report.add_source(&grafana_url);
+        report.add_expressions(&expressions);
+        report.add_findings(validate_expressions(&expressions, catalog.as_ref()));
+        report.add_findings(check_legend_formats(&expressions));
+
+        serde_json::to_value(report.finish())
+            .map(CommandOutput::Json)
+            .map_err(CliError::SerdeError)
+    }
+}
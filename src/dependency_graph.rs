@@ -0,0 +1,476 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use recording_rules::RecordingRule;
+use usage::*;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeKind {
+    Panel,
+    Alert,
+    /// A metric recorded by a recording rule.
+    Recorded,
+    /// A metric no recording rule records, e.g. of an exporter.
+    Raw,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct GraphNode {
+    pub kind: NodeKind,
+    pub label: String,
+    /// The uid of the dashboard of a panel, its title for dashboards without uid.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dashboard: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// An edge from a node to a metric it reads.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+}
+
+/// Panels and alerts, the metrics they read and the inputs of recorded metrics.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct DependencyGraph {
+    pub nodes: BTreeMap<String, GraphNode>,
+    pub edges: BTreeSet<Edge>,
+}
+
+fn metric_id(metric: &str) -> String {
+    format!("metric:{}", metric)
+}
+
+/// The node of the panel or alert an expression of `source` belongs to, `None` for other
+/// expressions.
+///
+/// Nodes are keyed by their position in the source, names and titles need not be unique.
+fn get_consumer(source: &str, pointer: &[Pointer]) -> Option<(String, GraphNode)> {
+    let find = |pointer_type: &str| pointer.iter().find(|p| p.pointer_type == pointer_type);
+
+    if let Some(panel) = find("panel") {
+        let dashboard = find("dashboard");
+        let dashboard_key = dashboard.map(|dashboard| {
+            dashboard
+                .uid
+                .clone()
+                .unwrap_or_else(|| dashboard.title.clone().unwrap_or_default())
+        });
+        let dashboard_title = dashboard
+            .and_then(|dashboard| dashboard.title.clone())
+            .unwrap_or_default();
+
+        // v4 panels without an id are told apart by row and position, not to collide with ids
+        let row_id = find("row").map(|row| row.id).unwrap_or_default();
+        let panel_key = match panel.index {
+            Some(index) => format!("#{}", index),
+            None => panel.id.to_string(),
+        };
+
+        return Some((
+            format!(
+                "panel:{}/{}/{}/{}",
+                source,
+                dashboard_key.clone().unwrap_or_default(),
+                row_id,
+                panel_key
+            ),
+            GraphNode {
+                kind: NodeKind::Panel,
+                label: format!(
+                    "{} / {}",
+                    dashboard_title,
+                    panel.title.clone().unwrap_or_default()
+                ),
+                dashboard: dashboard_key,
+                url: panel
+                    .entity_url
+                    .clone()
+                    .or_else(|| dashboard.and_then(|dashboard| dashboard.entity_url.clone())),
+            },
+        ));
+    }
+
+    let rule = pointer.last()?;
+    if rule.pointer_type != "alert" {
+        return None;
+    }
+    // e.g. group and rule index, prefixed with the manifest of kubernetes sources
+    let path: Vec<String> = pointer.iter().map(|pointer| pointer.id.to_string()).collect();
+
+    Some((
+        format!("alert:{}/{}", source, path.join("/")),
+        GraphNode {
+            kind: NodeKind::Alert,
+            label: rule.title.clone().unwrap_or_default(),
+            dashboard: None,
+            url: None,
+        },
+    ))
+}
+
+impl DependencyGraph {
+    /// Link the panels and alerts of the expressions to the metrics they read, and every recorded
+    /// metric to its inputs, transitively down to the raw metrics.
+    pub fn new(
+        expressions: &[Expression],
+        definitions: &BTreeMap<&str, Vec<&RecordingRule>>,
+    ) -> DependencyGraph {
+        let mut graph = DependencyGraph::default();
+        let mut queue: VecDeque<String> = VecDeque::new();
+
+        for expression in expressions {
+            let (id, node) = match get_consumer(&expression.context.source, &expression.pointer) {
+                Some(consumer) => consumer,
+                None => continue,
+            };
+            // expressions which can not be parsed are reported by validate
+            let metrics = get_used_metrics_from_expression(&expression.expression)
+                .unwrap_or_default();
+
+            for metric in metrics {
+                graph.edges.insert(Edge {
+                    from: id.clone(),
+                    to: metric_id(&metric),
+                });
+                queue.push_back(metric);
+            }
+            graph.nodes.insert(id, node);
+        }
+
+        queue.extend(definitions.keys().map(|metric| metric.to_string()));
+
+        while let Some(metric) = queue.pop_front() {
+            let id = metric_id(&metric);
+            if graph.nodes.contains_key(&id) {
+                continue;
+            }
+
+            let rules = definitions.get(metric.as_str());
+            for rule in rules.into_iter().flatten() {
+                for input in rule.inputs.iter() {
+                    graph.edges.insert(Edge {
+                        from: id.clone(),
+                        to: metric_id(input),
+                    });
+                    queue.push_back(input.clone());
+                }
+            }
+
+            graph.nodes.insert(
+                id,
+                GraphNode {
+                    kind: if rules.is_some() {
+                        NodeKind::Recorded
+                    } else {
+                        NodeKind::Raw
+                    },
+                    label: metric,
+                    dashboard: None,
+                    url: None,
+                },
+            );
+        }
+
+        graph
+    }
+
+    /// The nodes reachable from `start`, along the edges or against them.
+    fn reachable(&self, start: Vec<String>, upstream: bool) -> BTreeSet<String> {
+        let mut reached = BTreeSet::new();
+        let mut queue: VecDeque<String> = start.into_iter().collect();
+
+        while let Some(id) = queue.pop_front() {
+            if !reached.insert(id.clone()) {
+                continue;
+            }
+
+            for edge in self.edges.iter() {
+                match (upstream, edge.from == id, edge.to == id) {
+                    (false, true, _) => queue.push_back(edge.to.clone()),
+                    (true, _, true) => queue.push_back(edge.from.clone()),
+                    _ => (),
+                }
+            }
+        }
+
+        reached
+    }
+
+    fn subgraph(&self, ids: &BTreeSet<String>) -> DependencyGraph {
+        DependencyGraph {
+            nodes: self
+                .nodes
+                .iter()
+                .filter(|(id, _)| ids.contains(*id))
+                .map(|(id, node)| (id.clone(), node.clone()))
+                .collect(),
+            edges: self
+                .edges
+                .iter()
+                .filter(|edge| ids.contains(&edge.from) && ids.contains(&edge.to))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// The panels of the dashboard, by uid or title, and everything they depend on.
+    pub fn for_dashboard(&self, dashboard: &str) -> DependencyGraph {
+        let panels = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.dashboard.as_ref().map(|uid| uid.as_ref()) == Some(dashboard))
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        self.subgraph(&self.reachable(panels, false))
+    }
+
+    /// The metric, everything depending on it and, if it is recorded, its inputs.
+    pub fn for_metric(&self, metric: &str) -> DependencyGraph {
+        let mut ids = self.reachable(vec![metric_id(metric)], true);
+        ids.extend(self.reachable(vec![metric_id(metric)], false));
+
+        self.subgraph(&ids)
+    }
+
+    /// The graph in the graphviz dot language, e.g. for `dot -Tsvg`.
+    pub fn to_dot(&self) -> String {
+        let mut dot = "digraph dependencies {\n    rankdir=LR;\n".to_string();
+
+        for (id, node) in self.nodes.iter() {
+            let shape = match node.kind {
+                NodeKind::Panel => "shape=box",
+                NodeKind::Alert => "shape=hexagon",
+                NodeKind::Recorded => "shape=box, style=rounded",
+                NodeKind::Raw => "shape=ellipse",
+            };
+            let url = node
+                .url
+                .as_ref()
+                .map(|url| format!(", URL={}", dot_quote(url)))
+                .unwrap_or_default();
+
+            dot.push_str(&format!(
+                "    {} [label={}, {}{}];\n",
+                dot_quote(id),
+                dot_quote(&node.label),
+                shape,
+                url
+            ));
+        }
+
+        for edge in self.edges.iter() {
+            dot.push_str(&format!(
+                "    {} -> {};\n",
+                dot_quote(&edge.from),
+                dot_quote(&edge.to)
+            ));
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// The graph as a mermaid flowchart, e.g. for markdown rendered by github or gitlab.
+    pub fn to_mermaid(&self) -> String {
+        let mut mermaid = "graph LR\n".to_string();
+        // mermaid ids can not contain the colons and slashes of node ids
+        let ids: BTreeMap<&str, String> = self
+            .nodes
+            .keys()
+            .enumerate()
+            .map(|(index, id)| (id.as_ref(), format!("n{}", index)))
+            .collect();
+
+        for (id, node) in self.nodes.iter() {
+            let label = mermaid_quote(&node.label);
+            let shape = match node.kind {
+                NodeKind::Panel => format!("[{}]", label),
+                NodeKind::Alert => format!("{{{{{}}}}}", label),
+                NodeKind::Recorded => format!("([{}])", label),
+                NodeKind::Raw => format!("[({})]", label),
+            };
+            mermaid.push_str(&format!("    {}{}\n", ids[id.as_str()], shape));
+        }
+
+        for edge in self.edges.iter() {
+            if let (Some(from), Some(to)) = (ids.get(edge.from.as_str()), ids.get(edge.to.as_str()))
+            {
+                mermaid.push_str(&format!("    {} --> {}\n", from, to));
+            }
+        }
+
+        for (id, node) in self.nodes.iter() {
+            if let Some(ref url) = node.url {
+                mermaid.push_str(&format!(
+                    "    click {} href {}\n",
+                    ids[id.as_str()],
+                    mermaid_quote(url)
+                ));
+            }
+        }
+
+        mermaid
+    }
+}
+
+fn dot_quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn mermaid_quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "#quot;"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grafana::Dashboard;
+    use recording_rules::{get_definitions, get_recording_rules};
+    use rules::RuleFile;
+
+    fn from_source(source: &str, mut expressions: Vec<Expression>) -> Vec<Expression> {
+        for expression in expressions.iter_mut() {
+            expression.context.source = source.to_string();
+        }
+
+        expressions
+    }
+
+    fn rule_file(source: &str, content: &str) -> Vec<Expression> {
+        let rule_file: RuleFile = serde_yaml::from_str(content).expect("valid rule file");
+        from_source(source, get_expressions_from_rule_file(&rule_file))
+    }
+
+    fn expressions() -> Vec<Expression> {
+        let mut expressions = rule_file(
+            "a.yml",
+            "groups:
+- name: recorded
+  rules:
+  - record: job:up:sum
+    expr: sum by (job) (up)
+- name: alerts
+  rules:
+  - alert: Down
+    expr: job:up:sum == 0
+- name: more-alerts
+  rules:
+  - alert: Down
+    expr: absent(up)
+",
+        );
+        expressions.extend(rule_file(
+            "b.yml",
+            "groups:
+- name: alerts
+  rules:
+  - alert: Down
+    expr: node_load1 > 10
+",
+        ));
+
+        // a v4 dashboard, the second panel has no id and falls back to its index
+        let dashboard: Dashboard = serde_json::from_str(
+            r#"{"uid": "abc", "title": "Nodes", "rows": [{"title": "row", "panels": [
+                {"id": 1, "title": "Load", "datasource": "Prometheus",
+                 "targets": [{"expr": "node_load1", "refId": "A"}]},
+                {"title": "Up", "datasource": "Prometheus",
+                 "targets": [{"expr": "job:up:sum", "refId": "A"}]}
+            ]}]}"#,
+        ).expect("valid dashboard");
+        expressions.extend(from_source(
+            "grafana",
+            get_expressions_from_dashboard(&dashboard),
+        ));
+
+        expressions
+    }
+
+    fn graph(expressions: &[Expression]) -> DependencyGraph {
+        let rules = get_recording_rules(expressions);
+        DependencyGraph::new(expressions, &get_definitions(&rules))
+    }
+
+    fn edges(graph: &DependencyGraph) -> Vec<String> {
+        graph
+            .edges
+            .iter()
+            .map(|edge| format!("{} -> {}", edge.from, edge.to))
+            .collect()
+    }
+
+    #[test]
+    fn new() {
+        let graph = graph(&expressions());
+
+        // alerts sharing a name are keyed by source, group and rule index, panels without an
+        // id by their position, so none are merged
+        assert_eq!(
+            edges(&graph),
+            vec![
+                "alert:a.yml/1/0 -> metric:job:up:sum",
+                "alert:a.yml/2/0 -> metric:up",
+                "alert:b.yml/0/0 -> metric:node_load1",
+                "metric:job:up:sum -> metric:up",
+                "panel:grafana/abc/0/#1 -> metric:job:up:sum",
+                "panel:grafana/abc/0/1 -> metric:node_load1",
+            ]
+        );
+
+        let cases = vec![
+            ("alert:a.yml/1/0", NodeKind::Alert, "Down"),
+            ("alert:a.yml/2/0", NodeKind::Alert, "Down"),
+            ("alert:b.yml/0/0", NodeKind::Alert, "Down"),
+            ("panel:grafana/abc/0/1", NodeKind::Panel, "Nodes / Load"),
+            ("panel:grafana/abc/0/#1", NodeKind::Panel, "Nodes / Up"),
+            ("metric:job:up:sum", NodeKind::Recorded, "job:up:sum"),
+            ("metric:up", NodeKind::Raw, "up"),
+            ("metric:node_load1", NodeKind::Raw, "node_load1"),
+        ];
+        assert_eq!(graph.nodes.len(), cases.len());
+
+        for (id, kind, label) in cases {
+            let node = &graph.nodes[id];
+            assert_eq!((node.kind, node.label.as_str()), (kind, label), "{}", id);
+        }
+    }
+
+    #[test]
+    fn subgraphs() {
+        let graph = graph(&expressions());
+        let cases = vec![
+            (
+                graph.for_dashboard("abc"),
+                vec![
+                    "metric:job:up:sum -> metric:up",
+                    "panel:grafana/abc/0/#1 -> metric:job:up:sum",
+                    "panel:grafana/abc/0/1 -> metric:node_load1",
+                ],
+            ),
+            (graph.for_dashboard("missing"), vec![]),
+            (
+                graph.for_metric("job:up:sum"),
+                vec![
+                    "alert:a.yml/1/0 -> metric:job:up:sum",
+                    "metric:job:up:sum -> metric:up",
+                    "panel:grafana/abc/0/#1 -> metric:job:up:sum",
+                ],
+            ),
+            (
+                graph.for_metric("node_load1"),
+                vec![
+                    "alert:b.yml/0/0 -> metric:node_load1",
+                    "panel:grafana/abc/0/1 -> metric:node_load1",
+                ],
+            ),
+        ];
+
+        for (subgraph, expected) in cases {
+            assert_eq!(edges(&subgraph), expected);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::BTreeSet;
+
+use snapshot::CatalogSnapshot;
+use usage::MetricsUsage;
+
+/// Metrics which changed between two catalogs.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CatalogDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<RemovedMetric>,
+    pub label_changes: Vec<LabelChange>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RemovedMetric {
+    pub metric: String,
+    /// Dashboard targets and alerts which still use the metric.
+    pub used_by: Vec<MetricsUsage>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LabelChange {
+    pub metric: String,
+    pub added_labels: Vec<String>,
+    pub removed_labels: Vec<String>,
+}
+
+/// Compare the metric names and label names of two catalogs.
+pub fn diff_catalogs(old: &CatalogSnapshot, new: &CatalogSnapshot) -> CatalogDiff {
+    let mut diff = CatalogDiff::default();
+
+    for (name, old_metric) in old.metrics.iter() {
+        let new_metric = match new.metrics.get(name) {
+            Some(new_metric) => new_metric,
+            None => {
+                diff.removed.push(RemovedMetric {
+                    metric: name.clone(),
+                    used_by: vec![],
+                });
+                continue;
+            }
+        };
+
+        let old_labels: BTreeSet<&String> = old_metric.labels.keys().collect();
+        let new_labels: BTreeSet<&String> = new_metric.labels.keys().collect();
+
+        if old_labels != new_labels {
+            diff.label_changes.push(LabelChange {
+                metric: name.clone(),
+                added_labels: new_labels
+                    .difference(&old_labels)
+                    .map(|label| label.to_string())
+                    .collect(),
+                removed_labels: old_labels
+                    .difference(&new_labels)
+                    .map(|label| label.to_string())
+                    .collect(),
+            });
+        }
+    }
+
+    diff.added = new
+        .metrics
+        .keys()
+        .filter(|name| !old.metrics.contains_key(*name))
+        .cloned()
+        .collect();
+
+    diff
+}
+
+/// Attach the usages of every removed metric to the diff.
+pub fn add_removed_usages(diff: &mut CatalogDiff, usages: &[MetricsUsage]) {
+    for removed in diff.removed.iter_mut() {
+        removed.used_by = usages
+            .iter()
+            .filter(|usage| usage.metrics.contains(&removed.metric))
+            .cloned()
+            .collect();
+    }
+}
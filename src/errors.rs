@@ -1,18 +1,76 @@
+use std::io;
+use std::path::PathBuf;
+
 use hyper::http::Error as HttpError;
-use restson;
+use native_tls;
 
 quick_error! {
     #[derive(Debug)]
     pub enum ClientError {
-          RestError { client: &'static str, err: restson::Error } {
-            cause(err)
-            description("client error at")
-        }
         HttpError(err: HttpError) {
             from()
             description("http error")
             cause(err)
         }
+        UrlError(url: String) {
+            description("invalid url")
+            display("invalid url: {}", url)
+        }
+        RequestError(message: String) {
+            description("request error")
+            display("request error: {}", message)
+        }
+        TimeoutError {
+            description("request timed out")
+        }
+        Unauthorized { client: &'static str, status: u16, message: String } {
+            description("unauthorized")
+            display("{} rejected the credentials ({}): {}", client, status, message)
+        }
+        NotFound { client: &'static str, status: u16, message: String } {
+            description("not found")
+            display("{} could not find the resource ({}): {}", client, status, message)
+        }
+        BadQuery { client: &'static str, status: u16, message: String } {
+            description("bad query")
+            display("{} rejected the query ({}): {}", client, status, message)
+        }
+        Timeout { client: &'static str, status: Option<u16>, message: String } {
+            description("timeout")
+            display("{} timed out: {}", client, message)
+        }
+        TooManyRequests { client: &'static str, status: u16, message: String } {
+            description("too many requests")
+            display("{} is rate limiting requests ({}): {}", client, status, message)
+        }
+        ServerError { client: &'static str, status: u16, message: String } {
+            description("server error")
+            display("{} failed ({}): {}", client, status, message)
+        }
+        ApiError { client: &'static str, status: u16, message: String } {
+            description("api error")
+            display("{} responded with status {}: {}", client, status, message)
+        }
+        DeserializeError { client: &'static str, err: serde_json::Error } {
+            description("could not deserialize response")
+            display("could not deserialize {} response: {}", client, err)
+            cause(err)
+        }
+        TlsError(err: native_tls::Error) {
+            from()
+            description("tls error")
+            display("tls error: {}", err)
+            cause(err)
+        }
+        TlsConfigError(message: String) {
+            description("invalid tls configuration")
+            display("invalid tls configuration: {}", message)
+        }
+        CertificateError(path: PathBuf, err: io::Error) {
+            description("could not read certificate")
+            display("could not read certificate {}: {}", path.display(), err)
+            cause(err)
+        }
     }
 }
 
@@ -35,6 +93,11 @@ quick_error! {
             display(me) -> ("{}", me.description())
         }
 
+        InvalidArgument(name: String, value: String) {
+            description("The argument has an invalid value")
+            display(r#"Invalid value "{}" for argument "{}""#, value, name)
+        }
+
         ArgumentParseError {
             description("A command is required")
         }
@@ -46,6 +109,59 @@ quick_error! {
         }
 
 
+        ServerError(err: hyper::Error) {
+            description("server error")
+            display("server error: {}", err)
+            cause(err)
+        }
+
+        IoError(path: PathBuf, err: io::Error) {
+            description("io error")
+            display("io error at {}: {}", path.display(), err)
+            cause(err)
+        }
+
+        CacheMiss(key: String, url: String) {
+            description("not cached")
+            display("{} of {} is not cached, run without --offline first", key, url)
+        }
+
+        RuleFileError(path: PathBuf, err: serde_yaml::Error) {
+            description("invalid rule file")
+            display("invalid rule file {}: {}", path.display(), err)
+            cause(err)
+        }
+
+        PrometheusConfigError(path: PathBuf, message: String) {
+            description("invalid prometheus config")
+            display("invalid prometheus config {}: {}", path.display(), message)
+        }
+
+        AlertmanagerConfigError(path: PathBuf, message: String) {
+            description("invalid alertmanager config")
+            display("invalid alertmanager config {}: {}", path.display(), message)
+        }
+
+        AlertPolicyError(path: PathBuf, message: String) {
+            description("invalid alert policy")
+            display("invalid alert policy {}: {}", path.display(), message)
+        }
+
+        ManifestError(path: PathBuf, message: String) {
+            description("invalid kubernetes manifest")
+            display("invalid kubernetes manifest {}: {}", path.display(), message)
+        }
+
+        ExpositionError(source: String, line: usize, message: String) {
+            description("invalid exposition format")
+            display("invalid exposition format in {} line {}: {}", source, line, message)
+        }
+
+        UnsupportedSnapshotVersion(version: u32) {
+            description("unsupported snapshot version")
+            display("unsupported snapshot version {}, export the catalog again", version)
+        }
+
         SerdeError(err: serde_json::Error) {
             from()
             description("serde error")
@@ -0,0 +1,173 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use catalog::MetricCatalog;
+use errors::*;
+use sources::{get_expressions_from_sources, ExpressionSource};
+use usage::*;
+
+/// Findings of one validation run over the expressions of all sources.
+#[derive(Debug, Default)]
+pub struct ValidationSummary {
+    /// Targets with unknown metrics, by dashboard uid and panel title.
+    pub invalid_targets: BTreeMap<(String, String), usize>,
+    /// Alerts with unknown metrics, by alert name.
+    pub invalid_alerts: BTreeMap<String, usize>,
+    pub parse_errors: usize,
+    /// Metrics of the catalog that no expression uses.
+    pub unused_metrics: usize,
+}
+
+/// Validate the expressions of all sources against the catalog.
+pub fn validate_all(
+    sources: &[Box<ExpressionSource>],
+    catalog: &MetricCatalog,
+) -> Result<ValidationSummary, CliError> {
+    let expressions = get_expressions_from_sources(sources)?;
+
+    let mut summary = ValidationSummary::default();
+    let mut used_metrics: HashSet<String> = HashSet::new();
+
+    for expression in expressions.iter() {
+        let metrics = match get_used_metrics_from_expression(&expression.expression) {
+            Ok(metrics) => metrics,
+            Err(_) => {
+                summary.parse_errors += 1;
+                continue;
+            }
+        };
+
+        let invalid = metrics.iter().any(|metric| !catalog.has_metric(metric));
+        used_metrics.extend(metrics);
+        if !invalid {
+            continue;
+        }
+
+        let find = |pointer_type: &str| {
+            expression
+                .pointer
+                .iter()
+                .find(|pointer| pointer.pointer_type == pointer_type)
+        };
+
+        if let Some(dashboard) = find("dashboard") {
+            let uid = dashboard
+                .uid
+                .clone()
+                .unwrap_or_else(|| dashboard.id.to_string());
+            let panel = find("panel")
+                .and_then(|pointer| pointer.title.clone())
+                .unwrap_or_default();
+
+            *summary.invalid_targets.entry((uid, panel)).or_insert(0) += 1;
+        } else if let Some(alert) = find("alert") {
+            let name = alert.title.clone().unwrap_or_default();
+
+            *summary.invalid_alerts.entry(name).or_insert(0) += 1;
+        }
+    }
+
+    summary.unused_metrics = catalog
+        .metric_names()
+        .iter()
+        .filter(|metric| !used_metrics.contains(*metric))
+        .count();
+
+    Ok(summary)
+}
+
+/// Render the last successful summary and the state of the last run in the text exposition format.
+pub fn render_metrics(
+    summary: Option<&ValidationSummary>,
+    success: bool,
+    duration: Duration,
+    finished_at: SystemTime,
+) -> String {
+    let mut out = String::new();
+
+    if let Some(summary) = summary {
+        write_header(
+            &mut out,
+            "promqueen_invalid_targets",
+            "Dashboard targets using metrics unknown to prometheus.",
+        );
+        for (&(ref dashboard_uid, ref panel), count) in summary.invalid_targets.iter() {
+            let _ = writeln!(
+                out,
+                "promqueen_invalid_targets{{dashboard_uid=\"{}\",panel=\"{}\"}} {}",
+                escape_label_value(dashboard_uid),
+                escape_label_value(panel),
+                count
+            );
+        }
+
+        write_header(
+            &mut out,
+            "promqueen_invalid_alerts",
+            "Alert rules using metrics unknown to prometheus.",
+        );
+        for (alert, count) in summary.invalid_alerts.iter() {
+            let _ = writeln!(
+                out,
+                "promqueen_invalid_alerts{{alertname=\"{}\"}} {}",
+                escape_label_value(alert),
+                count
+            );
+        }
+
+        write_gauge(
+            &mut out,
+            "promqueen_parse_errors",
+            "Expressions which could not be parsed.",
+            summary.parse_errors as f64,
+        );
+        write_gauge(
+            &mut out,
+            "promqueen_unused_metrics",
+            "Metrics not used by any expression.",
+            summary.unused_metrics as f64,
+        );
+    }
+
+    write_gauge(
+        &mut out,
+        "promqueen_last_run_success",
+        "Whether the last validation run succeeded.",
+        if success { 1.0 } else { 0.0 },
+    );
+    write_gauge(
+        &mut out,
+        "promqueen_last_run_duration_seconds",
+        "Duration of the last validation run.",
+        duration.as_secs() as f64 + f64::from(duration.subsec_millis()) / 1000.0,
+    );
+    write_gauge(
+        &mut out,
+        "promqueen_last_run_timestamp_seconds",
+        "Unix time of the end of the last validation run.",
+        finished_at
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_secs() as f64)
+            .unwrap_or(0.0),
+    );
+
+    out
+}
+
+fn write_header(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+}
+
+fn write_gauge(out: &mut String, name: &str, help: &str, value: f64) {
+    write_header(out, name, help);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,435 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use errors::*;
+use prometheus::MetricMetadata;
+use snapshot::{CatalogSnapshot, MetricSnapshot, SNAPSHOT_VERSION};
+
+/// Samples of histograms, summaries and OpenMetrics families append these to the family name.
+static SAMPLE_SUFFIXES: &[&str] = &[
+    "_total", "_created", "_bucket", "_count", "_sum", "_gcount", "_gsum", "_info",
+];
+
+#[derive(Debug, Default)]
+struct Family {
+    metric_type: String,
+    help: String,
+    unit: String,
+}
+
+/// Read a dump of a `/metrics` endpoint from a file, or from stdin if the path is `-`.
+pub fn load_exposition(path: &Path) -> Result<CatalogSnapshot, CliError> {
+    let io_error = |err| CliError::IoError(path.to_path_buf(), err);
+
+    let (content, dumped_at) = if path == Path::new("-") {
+        let mut content = String::new();
+        io::stdin().read_to_string(&mut content).map_err(io_error)?;
+
+        (content, SystemTime::now())
+    } else {
+        let content = fs::read_to_string(path).map_err(io_error)?;
+        let modified = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .unwrap_or_else(|_| SystemTime::now());
+
+        (content, modified)
+    };
+
+    parse_exposition(
+        &path.display().to_string(),
+        &content,
+        dumped_at
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_secs())
+            .unwrap_or(0),
+    )
+}
+
+/// Parse the prometheus text exposition format or OpenMetrics into a catalog.
+///
+/// The catalog has the sample names, e.g. `http_request_duration_seconds_bucket`, as they are
+/// stored by prometheus. It is dated by the newest sample timestamp, `dumped_at` without any.
+pub fn parse_exposition(
+    source: &str,
+    content: &str,
+    dumped_at: u64,
+) -> Result<CatalogSnapshot, CliError> {
+    let open_metrics = content.lines().any(|line| line.trim_end() == "# EOF");
+
+    let mut families: HashMap<String, Family> = HashMap::new();
+    let mut metrics: BTreeMap<String, MetricSnapshot> = BTreeMap::new();
+    let mut newest_timestamp: Option<u64> = None;
+
+    for (line_number, line) in content.lines().enumerate() {
+        let error = |message: &str| {
+            CliError::ExpositionError(source.to_string(), line_number + 1, message.to_string())
+        };
+        let line = line.trim_end_matches('\r');
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if line.starts_with('#') {
+            parse_descriptor(line, &mut families).map_err(|message| error(&message))?;
+            continue;
+        }
+
+        let sample = parse_sample(line).map_err(|message| error(&message))?;
+
+        if let Some(timestamp) = sample.timestamp {
+            let seconds = if open_metrics {
+                timestamp as u64
+            } else {
+                (timestamp / 1000.0) as u64
+            };
+            newest_timestamp = newest_timestamp.max(Some(seconds));
+        }
+
+        let metric = metrics.entry(sample.name.clone()).or_default();
+        metric.series_count += 1;
+
+        for (label, value) in sample.labels {
+            metric.labels.entry(label).or_default().insert(value);
+        }
+
+        if metric.metadata.is_empty() {
+            if let Some(family) = find_family(&families, &sample.name) {
+                metric.metadata.push(MetricMetadata {
+                    metric_type: family.metric_type.clone(),
+                    help: family.help.clone(),
+                    unit: family.unit.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(CatalogSnapshot {
+        version: SNAPSHOT_VERSION,
+        source: source.to_string(),
+        created_at: newest_timestamp.unwrap_or(dumped_at),
+        metrics,
+    })
+}
+
+/// The family of a sample, by its name or its name without a sample suffix.
+fn find_family<'a>(families: &'a HashMap<String, Family>, name: &str) -> Option<&'a Family> {
+    families.get(name).or_else(|| {
+        SAMPLE_SUFFIXES
+            .iter()
+            .filter(|suffix| name.ends_with(*suffix))
+            .filter_map(|suffix| families.get(&name[..name.len() - suffix.len()]))
+            .next()
+    })
+}
+
+/// `# HELP`, `# TYPE` and `# UNIT` lines, other comments are ignored.
+fn parse_descriptor(line: &str, families: &mut HashMap<String, Family>) -> Result<(), String> {
+    let mut parts = line[1..].trim_start().splitn(3, ' ');
+    let keyword = parts.next().unwrap_or("");
+
+    if keyword != "HELP" && keyword != "TYPE" && keyword != "UNIT" {
+        return Ok(());
+    }
+
+    let name = parts
+        .next()
+        .filter(|name| is_metric_name(name))
+        .ok_or_else(|| format!("missing metric name after {}", keyword))?;
+    let value = parts.next().unwrap_or("").trim();
+    let family = families.entry(name.to_string()).or_default();
+
+    match keyword {
+        "HELP" => family.help = unescape(value),
+        "TYPE" => family.metric_type = value.to_string(),
+        _ => family.unit = value.to_string(),
+    }
+
+    Ok(())
+}
+
+/// Label names and values in the order of the sample line.
+type LabelPairs = Vec<(String, String)>;
+
+#[derive(Debug)]
+struct Sample {
+    name: String,
+    labels: LabelPairs,
+    timestamp: Option<f64>,
+}
+
+/// `name{label="value",...} value [timestamp] [# exemplar]`
+fn parse_sample(line: &str) -> Result<Sample, String> {
+    let name_end = line
+        .find(|c: char| c == '{' || c.is_whitespace())
+        .ok_or_else(|| "missing sample value".to_string())?;
+    let name = &line[..name_end];
+
+    if !is_metric_name(name) {
+        return Err(format!("invalid metric name {:?}", name));
+    }
+
+    let mut rest = &line[name_end..];
+    let mut labels = vec![];
+
+    if rest.starts_with('{') {
+        let (parsed, remainder) = parse_labels(&rest[1..])?;
+        labels = parsed;
+        rest = remainder;
+    }
+
+    // OpenMetrics exemplars follow the sample after ` # `
+    let rest = rest.split(" # ").next().unwrap_or("");
+    let mut fields = rest.split_whitespace();
+
+    let value = fields
+        .next()
+        .ok_or_else(|| "missing sample value".to_string())?;
+    if !is_sample_value(value) {
+        return Err(format!("invalid sample value {:?}", value));
+    }
+
+    let timestamp = match fields.next() {
+        Some(timestamp) => Some(
+            timestamp
+                .parse::<f64>()
+                .map_err(|_| format!("invalid timestamp {:?}", timestamp))?,
+        ),
+        None => None,
+    };
+
+    if fields.next().is_some() {
+        return Err("unexpected content after the timestamp".to_string());
+    }
+
+    Ok(Sample {
+        name: name.to_string(),
+        labels,
+        timestamp,
+    })
+}
+
+/// Parse the labels after `{`, returns them with the rest of the line after `}`.
+fn parse_labels(input: &str) -> Result<(LabelPairs, &str), String> {
+    let mut labels = vec![];
+    let mut rest = input.trim_start();
+
+    loop {
+        if rest.starts_with('}') {
+            return Ok((labels, &rest[1..]));
+        }
+
+        let equals = rest
+            .find('=')
+            .ok_or_else(|| "missing = after label name".to_string())?;
+        let label = rest[..equals].trim();
+
+        if !is_label_name(label) {
+            return Err(format!("invalid label name {:?}", label));
+        }
+
+        rest = rest[equals + 1..].trim_start();
+        if !rest.starts_with('"') {
+            return Err(format!("missing quoted value of label {}", label));
+        }
+
+        let (value, remainder) = parse_quoted(&rest[1..])
+            .ok_or_else(|| format!("unterminated value of label {}", label))?;
+        labels.push((label.to_string(), value));
+
+        rest = remainder.trim_start();
+        if rest.starts_with(',') {
+            rest = rest[1..].trim_start();
+        } else if !rest.starts_with('}') {
+            return Err("missing , or } after label value".to_string());
+        }
+    }
+}
+
+/// Read an escaped string up to the closing quote, returns it with the rest after the quote.
+fn parse_quoted(input: &str) -> Option<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = input.char_indices();
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &input[index + 1..])),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, escaped)) => value.push(escaped),
+                None => return None,
+            },
+            _ => value.push(c),
+        }
+    }
+
+    None
+}
+
+/// Unescape a `HELP` text in one pass, so `\\n` stays a backslash followed by `n`.
+/// Backslashes before other characters are kept.
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some(escaped) if escaped == '\\' || escaped == '"' => unescaped.push(escaped),
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+
+    unescaped
+}
+
+fn is_metric_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+fn is_label_name(name: &str) -> bool {
+    is_metric_name(name) && !name.contains(':')
+}
+
+fn is_sample_value(value: &str) -> bool {
+    match value {
+        "+Inf" | "-Inf" | "Inf" | "NaN" => true,
+        _ => value.parse::<f64>().is_ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> CatalogSnapshot {
+        parse_exposition("test", content, 42).expect("valid exposition")
+    }
+
+    fn error_line(content: &str) -> usize {
+        match parse_exposition("test", content, 42) {
+            Err(CliError::ExpositionError(_, line, _)) => line,
+            other => panic!("expected an exposition error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn help_and_type() {
+        let snapshot = parse(
+            "# HELP http_requests_total Requests.\n\
+             # TYPE http_requests_total counter\n\
+             http_requests_total{code=\"200\"} 3\n\
+             http_requests_total{code=\"500\"} 1\n",
+        );
+        let metric = &snapshot.metrics["http_requests_total"];
+
+        assert_eq!(
+            metric.metadata,
+            vec![MetricMetadata {
+                metric_type: "counter".to_string(),
+                help: "Requests.".to_string(),
+                unit: String::new(),
+            }]
+        );
+        assert_eq!(metric.series_count, 2);
+        assert_eq!(metric.labels["code"].len(), 2);
+        assert_eq!(snapshot.created_at, 42);
+    }
+
+    #[test]
+    fn histogram_samples_use_the_family() {
+        let snapshot = parse(
+            "# TYPE latency_seconds histogram\n\
+             latency_seconds_bucket{le=\"+Inf\"} 1\n\
+             latency_seconds_sum 0.5\n\
+             latency_seconds_count 1\n",
+        );
+
+        for name in &["latency_seconds_bucket", "latency_seconds_sum"] {
+            assert_eq!(snapshot.metrics[*name].metadata[0].metric_type, "histogram");
+        }
+        assert_eq!(snapshot.metrics.len(), 3);
+    }
+
+    #[test]
+    fn escapes() {
+        let cases = vec![
+            (r#"a\\b"#, r#"a\b"#),
+            (r#"line\nbreak"#, "line\nbreak"),
+            (r#"not\\nbreak"#, r#"not\nbreak"#),
+            (r#"kept\t"#, r#"kept\t"#),
+        ];
+        for (escaped, expected) in cases {
+            assert_eq!(unescape(escaped), expected, "help {:?}", escaped);
+        }
+
+        let snapshot = parse(r#"up{path="C:\\dir",quote="say \"hi\"",nl="a\nb"} 1"#);
+        let labels = &snapshot.metrics["up"].labels;
+
+        assert!(labels["path"].contains(r#"C:\dir"#));
+        assert!(labels["quote"].contains(r#"say "hi""#));
+        assert!(labels["nl"].contains("a\nb"));
+    }
+
+    #[test]
+    fn open_metrics() {
+        let snapshot = parse(
+            "# TYPE requests counter\n\
+             # UNIT requests requests\n\
+             requests_total 3 1700000000.5 # {trace_id=\"abc\"} 1\n\
+             requests_created 1600000000\n\
+             # EOF\n",
+        );
+
+        let total = &snapshot.metrics["requests_total"].metadata[0];
+        let created = &snapshot.metrics["requests_created"].metadata[0];
+
+        assert_eq!(total.metric_type, "counter");
+        assert_eq!(total.unit, "requests");
+        assert_eq!(created.metric_type, "counter");
+        // OpenMetrics timestamps are seconds, not milliseconds
+        assert_eq!(snapshot.created_at, 1_700_000_000);
+    }
+
+    #[test]
+    fn text_format_timestamps_are_milliseconds() {
+        assert_eq!(parse("up 1 1700000000000\n").created_at, 1_700_000_000);
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let cases = vec![
+            ("up 1\n# TYPE\n", 2),
+            ("up 1\n\nup{job=\"a\" 1\n", 3),
+            ("up{job=a} 1\n", 1),
+            ("up{job=\"a} 1\n", 1),
+            ("up{1job=\"a\"} 1\n", 1),
+            ("# HELP up Up.\nup\n", 2),
+            ("up one\n", 1),
+            ("up 1 later\n", 1),
+            ("up 1 2 3\n", 1),
+            ("1up 1\n", 1),
+        ];
+
+        for (content, line) in cases {
+            assert_eq!(error_line(content), line, "content {:?}", content);
+        }
+    }
+}
@@ -1,18 +1,72 @@
-use restson::{Error, RestClient, RestPath};
 
+use futures::Future;
+use serde::{Deserialize, Deserializer};
+use url::form_urlencoded;
+use url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+
+use api_client::{Api, ApiClient, ApiFuture, ClientBuilder, ClientOptions, FromApiClient};
 use errors::ClientError;
+use runtime;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Dashboard {
+    #[serde(default, deserialize_with = "null_as_default")]
     pub id: u32,
+    /// Only set since grafana v5.
+    pub uid: Option<String>,
     pub title: String,
 
+    /// Grafana v4 keeps the panels in rows.
     #[serde(default = "Vec::new")]
     pub rows: Vec<Row>,
+    /// Since grafana v5 panels are top-level, rows are panels of type `row`.
+    #[serde(default = "Vec::new")]
+    pub panels: Vec<Panel>,
+}
+
+impl Dashboard {
+    /// The rows with their panels. The panels of grafana v5+ dashboards belong to the row panel
+    /// before them, collapsed rows nest their panels. Panels before the first row form an
+    /// untitled row.
+    pub fn get_rows(&self) -> Vec<(&str, Vec<&Panel>)> {
+        let mut rows: Vec<(&str, Vec<&Panel>)> = self
+            .rows
+            .iter()
+            .map(|row| (row.title.as_ref(), row.panels.iter().collect()))
+            .collect();
+
+        let mut current: (&str, Vec<&Panel>) = ("", vec![]);
+        for panel in self.panels.iter() {
+            if panel.panel_type != "row" {
+                current.1.push(panel);
+                continue;
+            }
+
+            if !current.1.is_empty() || !current.0.is_empty() {
+                rows.push(current);
+            }
+            current = (panel.title.as_ref(), panel.panels.iter().collect());
+        }
+        if !current.1.is_empty() || !current.0.is_empty() {
+            rows.push(current);
+        }
+
+        rows
+    }
+}
+
+/// Exported dashboards have `"id": null`.
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(|value| value.unwrap_or_default())
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Row {
+    #[serde(default)]
     pub title: String,
 
     #[serde(default = "Vec::new")]
@@ -21,29 +75,110 @@ pub struct Row {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Panel {
+    /// Unique within the dashboard, missing in old grafana v4 dashboards.
+    pub id: Option<u32>,
+    #[serde(default)]
     pub title: String,
-    pub datasource: Option<String>,
+    #[serde(rename = "type", default)]
+    pub panel_type: String,
+    pub datasource: Option<DatasourceRef>,
 
     #[serde(default = "Vec::new")]
     pub targets: Vec<Target>,
+    /// The panels of a collapsed row.
+    #[serde(default = "Vec::new")]
+    pub panels: Vec<Panel>,
+}
+
+/// A datasource by name, or since grafana v8 by type and uid.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum DatasourceRef {
+    Name(String),
+    Ref {
+        #[serde(rename = "type")]
+        datasource_type: Option<String>,
+        uid: Option<String>,
+    },
+}
+
+impl DatasourceRef {
+    pub fn is_prometheus(&self) -> bool {
+        match self {
+            DatasourceRef::Name(name) => name == "Prometheus",
+            DatasourceRef::Ref {
+                datasource_type, ..
+            } => datasource_type.as_ref().map(|t| t.as_ref()) == Some("prometheus"),
+        }
+    }
+
+    /// The name or uid explore refers to the datasource by.
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            DatasourceRef::Name(name) => Some(name),
+            DatasourceRef::Ref { uid, .. } => uid.as_ref().map(|uid| uid.as_ref()),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Target {
     pub title: Option<String>,
     pub expr: Option<String>,
+    #[serde(rename = "refId")]
+    pub ref_id: Option<String>,
+    /// Series names like `{{pod}} - {{container}}`, placeholders are replaced by labels.
+    #[serde(rename = "legendFormat")]
+    pub legend_format: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct ExploreQuery<'a> {
+    expr: &'a str,
+    #[serde(rename = "refId", skip_serializing_if = "Option::is_none")]
+    ref_id: Option<&'a String>,
+}
+
+/// Path of a dashboard, relative to the grafana url.
+pub fn dashboard_path(uid: &str) -> String {
+    format!("/d/{}", encode_path_segment(uid))
+}
+
+/// Path of a dashboard showing only the panel.
+pub fn panel_path(uid: &str, panel_id: u32) -> String {
+    format!("/d/{}?viewPanel={}", encode_path_segment(uid), panel_id)
+}
+
+/// Path of explore, running the expression against the datasource of the last hour.
+pub fn explore_path(datasource: &str, expr: &str, ref_id: Option<&String>) -> String {
+    let left = ("now-1h", "now", datasource, ExploreQuery { expr, ref_id });
+
+    format!(
+        "/explore?left={}",
+        encode(&serde_json::to_string(&left).unwrap_or_default())
+    )
+}
+
+/// Encode a query parameter value, spaces become `+`.
+fn encode(value: &str) -> String {
+    form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
+
+/// Encode a path segment, spaces become `%20` and `/` is escaped.
+fn encode_path_segment(value: &str) -> String {
+    utf8_percent_encode(value, PATH_SEGMENT_ENCODE_SET).collect()
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DashboardSearchResult {
-    id: u32,
+    pub id: u32,
     #[serde(rename = "isStarred")]
-    is_starred: bool,
-    tags: Vec<String>,
-    title: String,
+    pub is_starred: bool,
+    pub tags: Vec<String>,
+    pub title: String,
     #[serde(rename = "type")]
-    dashboard_type: String,
-    uri: String,
+    pub dashboard_type: String,
+    pub uri: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -57,69 +192,142 @@ struct GetDashboard {
     dashboard: Dashboard,
 }
 
-impl RestPath<()> for SearchDashboards {
-    fn get_path(_: ()) -> Result<String, Error> {
-        Ok(String::from("api/search"))
-    }
+/// Grafana's api wraps the dashboard, exported dashboards are not.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum DashboardJson {
+    Wrapped { dashboard: Dashboard },
+    Plain(Dashboard),
 }
 
-impl RestPath<String> for GetDashboard {
-    fn get_path(uri: String) -> Result<String, Error> {
-        Ok(format!("api/dashboards/{}", uri))
+/// Parse a dashboard as returned by the api or as exported from grafana.
+pub fn parse_dashboard(content: &[u8]) -> Result<Dashboard, serde_json::Error> {
+    match serde_json::from_slice(content)? {
+        DashboardJson::Wrapped { dashboard } | DashboardJson::Plain(dashboard) => Ok(dashboard),
     }
 }
 
+/// Grafana api client, clones share connections.
+///
+/// The blocking methods panic on the threads of `runtime::executor()`, futures running there use
+/// the `fetch_*` methods.
+#[derive(Clone)]
 pub struct GrafanaClient {
-    client: RestClient,
+    client: ApiClient,
 }
 
-fn to_grafana_error(err: Error) -> ClientError {
-    ClientError::RestError {
-        client: "grafana",
-        err: err,
+impl FromApiClient for GrafanaClient {
+    const API: Api = Api::Grafana;
+
+    fn from_api_client(client: ApiClient) -> GrafanaClient {
+        GrafanaClient { client }
+    }
+}
+
+impl ClientBuilder<GrafanaClient> {
+    pub fn api_key(self, api_key: &str) -> ClientBuilder<GrafanaClient> {
+        self.bearer_token(api_key)
     }
 }
 
 impl GrafanaClient {
-    pub fn new(url: String, api_key: Option<String>) -> Result<GrafanaClient, ClientError> {
-        RestClient::new(&url)
-            .and_then(|mut client| {
-                if api_key.is_some() {
-                    let mut auth_value = "Bearer ".to_owned();
-                    auth_value.push_str(&api_key.unwrap());
-                    client.set_header("Authorization", &auth_value)?;
-                }
+    pub fn new(
+        url: String,
+        api_key: Option<String>,
+        options: &ClientOptions,
+    ) -> Result<GrafanaClient, ClientError> {
+        let mut builder = GrafanaClient::builder(url).options(options.clone());
 
-                client.set_header("Accept", "application/json")?;
-                client.set_header("Content-Type", "application/json")?;
+        if let Some(api_key) = api_key {
+            builder = builder.api_key(&api_key);
+        }
 
-                Ok(GrafanaClient { client })
-            }).map_err(to_grafana_error)
+        builder.build()
     }
-    pub fn find_all_dashboards(mut self) -> Result<Vec<DashboardSearchResult>, ClientError> {
-        let result: Result<SearchDashboards, Error> = self.client.get(());
-        result
-            .map(|result| match result {
-                SearchDashboards::Array(result) => result,
-            }).map_err(to_grafana_error)
+
+    pub fn builder<U: Into<String>>(url: U) -> ClientBuilder<GrafanaClient> {
+        ClientBuilder::new(url)
+    }
+
+    pub fn find_all_dashboards(&self) -> Result<Vec<DashboardSearchResult>, ClientError> {
+        runtime::block_on(self.fetch_dashboard_list())
     }
 
     pub fn get_dashboard(
-        mut self,
-        search_result: DashboardSearchResult,
+        &self,
+        search_result: &DashboardSearchResult,
     ) -> Result<Dashboard, ClientError> {
-        let result: Result<GetDashboard, Error> = self.client.get(search_result.uri);
+        runtime::block_on(self.fetch_dashboard(&search_result.uri))
+    }
+
+    pub fn get_dashboard_by_uri(&self, uri: String) -> Result<Dashboard, ClientError> {
+        runtime::block_on(self.fetch_dashboard(&uri))
+    }
+
+    /// Fetch the dashboards with at most `concurrency` requests in flight, in the order of `uris`.
+    pub fn get_dashboards(
+        &self,
+        uris: Vec<String>,
+        concurrency: usize,
+    ) -> Result<Vec<Dashboard>, ClientError> {
+        let client = self.clone();
+
+        runtime::block_on(runtime::map_concurrent(uris, concurrency, move |uri| {
+            client.fetch_dashboard(&uri)
+        }))
+    }
+
+    /// Fetch every dashboard of the instance, folders are skipped.
+    pub fn get_all_dashboards(&self, concurrency: usize) -> Result<Vec<Dashboard>, ClientError> {
+        let uris = self
+            .find_all_dashboards()?
+            .into_iter()
+            .filter(|result| result.dashboard_type == "dash-db")
+            .map(|result| result.uri)
+            .collect();
 
-        result
-            .map(|result| result.dashboard)
-            .map_err(to_grafana_error)
+        self.get_dashboards(uris, concurrency)
     }
 
-    pub fn get_dashboard_by_uri(mut self, uri: String) -> Result<Dashboard, ClientError> {
-        let result: Result<GetDashboard, Error> = self.client.get(uri);
+    pub fn fetch_dashboard_list(&self) -> ApiFuture<Vec<DashboardSearchResult>> {
+        Box::new(
+            self.client
+                .get_json("api/search", None)
+                .map(|result| match result {
+                    SearchDashboards::Array(result) => result,
+                }),
+        )
+    }
+
+    pub fn fetch_dashboard(&self, uri: &str) -> ApiFuture<Dashboard> {
+        let path = format!("api/dashboards/{}", uri);
+
+        Box::new(
+            self.client
+                .get_json(&path, None)
+                .map(|result: GetDashboard| result.dashboard),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths() {
+        let cases = vec![
+            (dashboard_path("abc-123"), "/d/abc-123"),
+            (dashboard_path("my dashboard"), "/d/my%20dashboard"),
+            (dashboard_path("a/b?c#d"), "/d/a%2Fb%3Fc%23d"),
+            (
+                panel_path("my dashboard", 4),
+                "/d/my%20dashboard?viewPanel=4",
+            ),
+        ];
 
-        result
-            .map(|result| result.dashboard)
-            .map_err(to_grafana_error)
+        for (path, expected) in cases {
+            assert_eq!(path, expected);
+        }
     }
 }
@@ -1,22 +1,32 @@
 use std::time::Duration;
 
-use futures::future::Either;
-use futures::{Future, Stream};
+use futures::{future, Future, Stream};
 use hyper::header::*;
 use hyper::{Client, Request};
-use hyper_tls::HttpsConnector;
-use restson::Error;
-use tokio_core::reactor::Timeout;
+use tokio::timer::Timeout;
 use url::Url;
 
+use errors::ClientError;
+use tls::{HttpsConnector, TlsConfig};
+
 pub use hyper::Method;
-pub use restson::Query;
+
+pub const DEFAULT_TIMEOUT_SECS: u64 = 30;
+/// Timeouts are applied with millisecond precision.
+pub const MIN_TIMEOUT_SECS: f64 = 0.001;
+
+/// Query parameters as key value pairs.
+pub type Query<'a> = [(&'a str, &'a str)];
+
+/// A response with the complete body.
+pub type ResponseFuture = Box<Future<Item = hyper::Response<String>, Error = ClientError> + Send>;
 
 static VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
+/// Cheap to clone, all clones share the connection pool.
+#[derive(Clone)]
 pub struct HttpClient {
-    core: tokio_core::reactor::Core,
-    client: Client<HttpsConnector<hyper::client::HttpConnector>>,
+    client: Client<HttpsConnector>,
     timeout: Duration,
     baseurl: Url,
     headers: Option<HeaderMap>,
@@ -24,46 +34,59 @@ pub struct HttpClient {
 
 impl HttpClient {
     /// Construct new client to make HTTP requests.
+    ///
+    /// The returned futures have to run on a tokio runtime, e.g. with `runtime::block_on`.
     pub fn new<U: Into<String>>(
         url: U,
         headers: Option<HeaderMap>,
         timeout: Option<Duration>,
-    ) -> Result<HttpClient, Error> {
-        let core = tokio_core::reactor::Core::new().map_err(|_| Error::HttpClientError)?;
-
-        let https = HttpsConnector::new(4).map_err(|_| Error::HttpClientError)?;
+        tls: &TlsConfig,
+    ) -> Result<HttpClient, ClientError> {
+        let https = HttpsConnector::new(tls)?;
         let client = Client::builder().build(https);
 
-        let baseurl = Url::parse(&url.into()).map_err(|_| Error::UrlError)?;
+        let url = url.into();
+        let baseurl = Url::parse(&url).map_err(|_| ClientError::UrlError(url))?;
 
         Ok(HttpClient {
-            core,
             client,
-            // For some reason using u32::MAX or u64::MAX causes request error inside
-            // tokio/hyper. Use 1 year as default which is sufficiently large for "no timeout"
-            timeout: timeout.unwrap_or(Duration::from_secs(31_556_926)),
+            timeout: timeout.unwrap_or(Duration::from_secs(DEFAULT_TIMEOUT_SECS)),
             headers,
             baseurl,
         })
     }
 
     pub fn get<S: Into<String>>(
-        &mut self,
+        &self,
         path: S,
         params: Option<&Query>,
         headers: Option<HeaderMap>,
-    ) -> Result<hyper::Response<String>, Error> {
+    ) -> ResponseFuture {
         self.request(Method::GET, path, params, None, headers)
     }
 
     pub fn request<S: Into<String>>(
-        &mut self,
+        &self,
         method: Method,
         path: S,
         params: Option<&Query>,
         body: Option<S>,
         headers: Option<HeaderMap>,
-    ) -> Result<hyper::Response<String>, Error> {
+    ) -> ResponseFuture {
+        match self.build_request(method, path, params, body, headers) {
+            Ok(request) => self.send(request),
+            Err(err) => Box::new(future::err(err)),
+        }
+    }
+
+    fn build_request<S: Into<String>>(
+        &self,
+        method: Method,
+        path: S,
+        params: Option<&Query>,
+        body: Option<S>,
+        headers: Option<HeaderMap>,
+    ) -> Result<Request<hyper::Body>, ClientError> {
         let mut request = Request::new(hyper::Body::empty());
         let mut url = self.baseurl.clone();
 
@@ -79,7 +102,7 @@ impl HttpClient {
         *request.uri_mut() = url
             .as_str()
             .parse::<hyper::Uri>()
-            .map_err(|_| Error::UrlError)?;
+            .map_err(|_| ClientError::UrlError(url.to_string()))?;
 
         *request.headers_mut() = self.combine_headers(headers)?;
 
@@ -87,8 +110,8 @@ impl HttpClient {
             let b = body.into();
 
             if b != "null" {
-                let len =
-                    HeaderValue::from_str(&b.len().to_string()).map_err(|_| Error::RequestError)?;
+                let len = HeaderValue::from_str(&b.len().to_string())
+                    .map_err(|err| ClientError::RequestError(err.to_string()))?;
                 request.headers_mut().insert(CONTENT_LENGTH, len);
                 request.headers_mut().insert(
                     CONTENT_TYPE,
@@ -99,32 +122,32 @@ impl HttpClient {
             }
         }
 
-        //debug!("{} {}", req.method(), req.uri());
-        //trace!("{:?}", req);
+        Ok(request)
+    }
+
+    fn send(&self, request: Request<hyper::Body>) -> ResponseFuture {
+        debug!("{} {}", request.method(), request.uri());
 
         let response = self.client.request(request).and_then(|res| {
-            //trace!("response headers: {:?}", res.headers());
             let (parts, body) = res.into_parts();
 
-            body.concat2()
-                .map(|chunk| (parts, String::from_utf8_lossy(&chunk).to_string()))
+            body.concat2().map(|chunk| {
+                hyper::Response::from_parts(parts, String::from_utf8_lossy(&chunk).to_string())
+            })
         });
 
-        let timeout =
-            Timeout::new(self.timeout, &self.core.handle()).map_err(|_| Error::RequestError)?;
-        let work = response.select2(timeout).then(|res| match res {
-            Ok(Either::A((got, _))) => Ok(got),
-            Ok(Either::B((_, _))) => Err(Error::TimeoutError),
-            Err(_) => Err(Error::RequestError),
-        });
-
-        match self.core.run(work) {
-            Ok((parts, body)) => Ok(hyper::Response::from_parts(parts, body)),
-            Err(e) => Err(e),
-        }
+        Box::new(Timeout::new(response, self.timeout).map_err(|err| {
+            if err.is_elapsed() {
+                ClientError::TimeoutError
+            } else if let Some(err) = err.into_inner() {
+                ClientError::RequestError(err.to_string())
+            } else {
+                ClientError::RequestError("timer error".to_string())
+            }
+        }))
     }
 
-    pub fn combine_headers(&self, headers: Option<HeaderMap>) -> Result<HeaderMap, Error> {
+    pub fn combine_headers(&self, headers: Option<HeaderMap>) -> Result<HeaderMap, ClientError> {
         let mut result = HeaderMap::new();
 
         let add_headers = |r: &mut HeaderMap, ref headers: Option<HeaderMap>| {
@@ -141,8 +164,8 @@ impl HttpClient {
         if !result.contains_key(USER_AGENT) {
             result.insert(
                 USER_AGENT,
-                HeaderValue::from_str(&("restson/".to_owned() + VERSION))
-                    .map_err(|_| Error::RequestError)?,
+                HeaderValue::from_str(&("promqueen/".to_owned() + VERSION))
+                    .map_err(|err| ClientError::RequestError(err.to_string()))?,
             );
         }
 
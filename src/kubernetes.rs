@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use errors::*;
+use grafana::parse_dashboard;
+use rules::RuleFile;
+use sources::ExpressionSource;
+use usage::*;
+
+/// The label the grafana sidecar selects dashboard ConfigMaps by.
+pub const DEFAULT_DASHBOARD_LABEL: &str = "grafana_dashboard";
+
+#[derive(Deserialize, Debug, Default)]
+struct ObjectMeta {
+    name: Option<String>,
+    namespace: Option<String>,
+    /// Only the names are looked at, values may be numbers like `grafana_dashboard: 1`.
+    labels: Option<BTreeMap<String, serde_yaml::Value>>,
+}
+
+impl ObjectMeta {
+    fn qualified_name(&self) -> String {
+        let name = self.name.clone().unwrap_or_default();
+
+        match self.namespace {
+            Some(ref namespace) => format!("{}/{}", namespace, name),
+            None => name,
+        }
+    }
+
+    fn has_label(&self, label: &str) -> bool {
+        match self.labels {
+            Some(ref labels) => labels.contains_key(label),
+            None => false,
+        }
+    }
+}
+
+/// The fields of the manifests promqueen reads, empty fields like `data:` are null.
+#[derive(Deserialize, Debug)]
+struct Manifest {
+    metadata: Option<ObjectMeta>,
+    spec: Option<serde_yaml::Value>,
+    data: Option<BTreeMap<String, serde_yaml::Value>>,
+    /// Set for `kind: List`, e.g. from `kubectl get -o yaml`.
+    items: Option<Vec<serde_yaml::Value>>,
+}
+
+/// Expressions of `PrometheusRule` resources and dashboard ConfigMaps in multi-document yaml,
+/// e.g. rendered helm charts or kustomize output.
+pub fn get_expressions_from_manifests(
+    content: &[u8],
+    dashboard_label: &str,
+) -> Result<Vec<Expression>, String> {
+    let mut expressions = vec![];
+
+    for (index, document) in serde_yaml::Deserializer::from_slice(content).enumerate() {
+        let document =
+            serde_yaml::Value::deserialize(document).map_err(|err| err.to_string())?;
+
+        get_expressions_from_document(document, index, dashboard_label, &mut expressions)
+            .map_err(|err| format!("document {}: {}", index, err))?;
+    }
+
+    Ok(expressions)
+}
+
+/// Only `PrometheusRule`, `ConfigMap` and `List` manifests are deserialized, other resources
+/// are skipped whatever their content.
+fn get_expressions_from_document(
+    document: serde_yaml::Value,
+    index: usize,
+    dashboard_label: &str,
+    expressions: &mut Vec<Expression>,
+) -> Result<(), String> {
+    let kind = match document.get("kind").and_then(|kind| kind.as_str()) {
+        Some(kind @ "PrometheusRule") | Some(kind @ "ConfigMap") | Some(kind @ "List") => {
+            kind.to_string()
+        }
+        _ => return Ok(()),
+    };
+
+    let manifest: Manifest =
+        serde_yaml::from_value(document).map_err(|err| format!("{}: {}", kind, err))?;
+    let metadata = manifest.metadata.unwrap_or_default();
+    let name = metadata.qualified_name();
+
+    match kind.as_ref() {
+        "List" => {
+            let pointer = Pointer::new(index as u32, Some(name), "list");
+            let mut items = vec![];
+
+            for (item_index, item) in manifest.items.unwrap_or_default().into_iter().enumerate() {
+                get_expressions_from_document(item, item_index, dashboard_label, &mut items)
+                    .map_err(|err| format!("item {}: {}", item_index, err))?;
+            }
+
+            expressions.extend(prefix(pointer, items));
+        }
+        "PrometheusRule" => {
+            let spec = manifest
+                .spec
+                .filter(|spec| !spec.is_null())
+                .unwrap_or_else(|| serde_yaml::Value::Mapping(serde_yaml::Mapping::new()));
+            let rule_file: RuleFile = serde_yaml::from_value(spec)
+                .map_err(|err| format!("PrometheusRule {}: {}", name, err))?;
+            let pointer = Pointer::new(index as u32, Some(name), "prometheusrule");
+
+            expressions.extend(prefix(pointer, get_expressions_from_rule_file(&rule_file)));
+        }
+        _ if metadata.has_label(dashboard_label) => {
+            let pointer = Pointer::new(index as u32, Some(name.clone()), "configmap");
+
+            for (key, json) in manifest.data.unwrap_or_default().iter() {
+                let json = match json.as_str() {
+                    Some(json) if key.ends_with(".json") => json,
+                    _ => continue,
+                };
+
+                let dashboard = parse_dashboard(json.as_bytes())
+                    .map_err(|err| format!("ConfigMap {} key {}: {}", name, key, err))?;
+
+                expressions.extend(prefix(
+                    pointer.clone(),
+                    get_expressions_from_dashboard(&dashboard),
+                ));
+            }
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
+
+fn prefix(pointer: Pointer, expressions: Vec<Expression>) -> Vec<Expression> {
+    expressions
+        .into_iter()
+        .map(|mut expression| {
+            expression.pointer.insert(0, pointer.clone());
+            expression
+        }).collect()
+}
+
+/// A kubernetes manifest file, or stdin if the path is `-`.
+pub struct ManifestFileSource {
+    path: PathBuf,
+    dashboard_label: String,
+}
+
+impl ManifestFileSource {
+    pub fn new(path: PathBuf, dashboard_label: String) -> ManifestFileSource {
+        ManifestFileSource {
+            path,
+            dashboard_label,
+        }
+    }
+}
+
+impl ExpressionSource for ManifestFileSource {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn get_expressions(&self) -> Result<Vec<Expression>, CliError> {
+        let io_error = |err| CliError::IoError(self.path.clone(), err);
+
+        let content = if self.path == Path::new("-") {
+            let mut content = vec![];
+            io::stdin().read_to_end(&mut content).map_err(io_error)?;
+            content
+        } else {
+            fs::read(&self.path).map_err(io_error)?
+        };
+
+        get_expressions_from_manifests(&content, &self.dashboard_label)
+            .map_err(|message| CliError::ManifestError(self.path.clone(), message))
+    }
+}
@@ -1,30 +1,66 @@
 extern crate clap;
 extern crate promql;
-extern crate restson;
 extern crate serde;
 extern crate serde_json;
 extern crate serde_yaml;
 extern crate nom;
 
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate log;
 #[macro_use]
 extern crate quick_error;
 #[macro_use]
+extern crate schemars;
+#[macro_use]
 extern crate serde_derive;
 extern crate scraper;
 
-extern crate tokio_core;
+extern crate tokio;
 extern crate hyper;
 extern crate hyper_tls;
+extern crate native_tls;
 extern crate futures;
 extern crate url;
+extern crate rand;
+extern crate base64;
+extern crate dirs;
+extern crate glob;
+extern crate regex;
 
 
+pub mod alertmanager;
+pub mod api_client;
+pub mod api_server;
+pub mod cache;
+pub mod catalog;
 pub mod cli;
 pub mod commands;
+pub mod dependency_graph;
+pub mod diff;
 pub mod errors;
+pub mod exporter;
+pub mod exposition;
 pub mod grafana;
+pub mod kubernetes;
+pub mod naming;
+pub mod output_labels;
+pub mod policy;
 pub mod prometheus;
+pub mod prometheus_config;
+pub mod recording_rules;
+pub mod relabel;
+pub mod report;
+pub mod retry;
+pub mod rules;
+pub mod runtime;
+pub mod server;
+pub mod snapshot;
+pub mod sources;
+pub mod templates;
 pub mod usage;
 pub mod http_client;
+pub mod tls;
 
 pub use errors::*;
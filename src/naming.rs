@@ -0,0 +1,334 @@
+use std::collections::BTreeSet;
+
+use regex::Regex;
+
+use catalog::MetricCatalog;
+use report::{Finding, Severity};
+
+lazy_static! {
+    static ref SNAKE_CASE: Regex = Regex::new("^[a-z][a-z0-9_]*$").unwrap();
+}
+
+/// Units with the base unit to use instead, by the name part they appear as.
+static NON_BASE_UNITS: &[(&str, &str)] = &[
+    ("ms", "seconds"),
+    ("msec", "seconds"),
+    ("milliseconds", "seconds"),
+    ("microseconds", "seconds"),
+    ("nanoseconds", "seconds"),
+    ("minutes", "seconds"),
+    ("hours", "seconds"),
+    ("days", "seconds"),
+    ("kb", "bytes"),
+    ("kilobytes", "bytes"),
+    ("mb", "bytes"),
+    ("megabytes", "bytes"),
+    ("gb", "bytes"),
+    ("gigabytes", "bytes"),
+    ("percent", "ratio"),
+    ("celsius_degrees", "celsius"),
+];
+
+/// Suffixes of the series prometheus derives from histograms and summaries.
+static DERIVED_SUFFIXES: &[&str] = &["_bucket", "_count", "_sum", "_created"];
+
+static GAUGE_TYPE_SUFFIXES: &[&str] = &["_gauge", "_counter", "_count", "_sum", "_bucket"];
+
+/// The naming problems of a metric, by rule id.
+///
+/// Names with `:` are recording rules, they follow their own conventions.
+pub fn check_metric_name(
+    metric: &str,
+    types: &BTreeSet<String>,
+    labels: Option<&BTreeSet<String>>,
+) -> Vec<(&'static str, String)> {
+    let mut problems = vec![];
+
+    if metric.contains(':') {
+        return problems;
+    }
+
+    if !SNAKE_CASE.is_match(metric) {
+        problems.push(("metric-name-case", "the name is not snake_case".to_string()));
+    }
+
+    let lowercase = metric.to_lowercase();
+    let parts: Vec<&str> = lowercase.split('_').collect();
+    for &(unit, base_unit) in NON_BASE_UNITS.iter() {
+        let unit_parts: Vec<&str> = unit.split('_').collect();
+        if parts.windows(unit_parts.len()).any(|window| window == &unit_parts[..]) {
+            problems.push((
+                "metric-name-unit",
+                format!("use the base unit {} instead of {}", base_unit, unit),
+            ));
+        }
+    }
+
+    let is_counter = types.contains("counter");
+    let is_gauge = types.contains("gauge");
+    let is_derived = DERIVED_SUFFIXES.iter().any(|suffix| metric.ends_with(suffix));
+
+    if metric.ends_with("_total") && !types.is_empty() && !is_counter {
+        problems.push((
+            "metric-name-total",
+            format!(
+                "_total is reserved for counters, the metric is a {}",
+                types.iter().cloned().collect::<Vec<_>>().join(", ")
+            ),
+        ));
+    }
+
+    if is_counter && !is_derived && !metric.ends_with("_total") {
+        problems.push((
+            "metric-name-total",
+            "counters should end in _total".to_string(),
+        ));
+    }
+
+    if is_gauge {
+        if let Some(suffix) = GAUGE_TYPE_SUFFIXES
+            .iter()
+            .find(|suffix| metric.ends_with(*suffix))
+        {
+            problems.push((
+                "metric-name-type-suffix",
+                format!("gauges should not end in {}", suffix),
+            ));
+        }
+    }
+
+    for label in labels.into_iter().flatten() {
+        if label.starts_with("__") {
+            problems.push((
+                "label-name-reserved",
+                format!("label {} starts with __, which is reserved for internal use", label),
+            ));
+        } else if label == "exported_job" || label == "exported_instance" {
+            problems.push((
+                "label-name-reserved",
+                format!(
+                    "the exporter sets {}, prometheus renamed it to {}",
+                    label.trim_start_matches("exported_"),
+                    label
+                ),
+            ));
+        } else if label == "le" && !metric.ends_with("_bucket") {
+            problems.push((
+                "label-name-reserved",
+                "label le is reserved for histogram buckets".to_string(),
+            ));
+        } else if label == "quantile" && !types.contains("summary") && !types.is_empty() {
+            problems.push((
+                "label-name-reserved",
+                "label quantile is reserved for summaries".to_string(),
+            ));
+        } else if !SNAKE_CASE.is_match(label) {
+            problems.push((
+                "label-name-case",
+                format!("label {} is not snake_case", label),
+            ));
+        }
+    }
+
+    problems
+}
+
+/// Naming findings for every metric of the catalog read from `source`, once per job exposing
+/// it, or once without a job if the catalog does not know it.
+pub fn lint_catalog(source: &str, catalog: &MetricCatalog) -> Vec<Finding> {
+    let mut findings = vec![];
+
+    for metric in catalog.metric_names() {
+        let labels = catalog.get_labels(&metric);
+        let problems = check_metric_name(&metric, &catalog.get_types(&metric), labels.as_ref());
+        if problems.is_empty() {
+            continue;
+        }
+
+        let jobs = catalog.get_jobs(&metric);
+        let jobs: Vec<Option<&str>> = if jobs.is_empty() {
+            vec![None]
+        } else {
+            jobs.iter().map(|job| Some(job.as_str())).collect()
+        };
+
+        for job in jobs {
+            findings.extend(problems.iter().map(|&(rule_id, ref message)| {
+                Finding::for_metric(
+                    rule_id,
+                    Severity::Warning,
+                    message.clone(),
+                    source,
+                    job,
+                    &metric,
+                )
+            }));
+        }
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use snapshot::{CatalogSnapshot, MetricSnapshot, SNAPSHOT_VERSION};
+    use std::collections::BTreeMap;
+
+    /// Metric, types, labels and the expected rule ids.
+    type Case = (
+        &'static str,
+        &'static [&'static str],
+        Option<&'static [&'static str]>,
+        Vec<&'static str>,
+    );
+
+    fn set(values: &[&str]) -> BTreeSet<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn check_metric_name() {
+        let cases: Vec<Case> = vec![
+            (
+                "http_requests_total",
+                &["counter"],
+                Some(&["job", "code"]),
+                vec![],
+            ),
+            ("node_memory_bytes", &["gauge"], None, vec![]),
+            // recording rules follow their own conventions
+            ("job:http_requests:rate5m", &[], None, vec![]),
+            (
+                "httpRequestsTotal",
+                &["counter"],
+                None,
+                vec!["metric-name-case", "metric-name-total"],
+            ),
+            (
+                "request_duration_ms",
+                &["gauge"],
+                None,
+                vec!["metric-name-unit"],
+            ),
+            ("cpu_percent", &[], None, vec!["metric-name-unit"]),
+            (
+                "temperature_celsius_degrees",
+                &[],
+                None,
+                vec!["metric-name-unit"],
+            ),
+            // units are whole name parts
+            ("items_msgs", &[], None, vec![]),
+            (
+                "queue_length_total",
+                &["gauge"],
+                None,
+                vec!["metric-name-total"],
+            ),
+            (
+                "http_requests",
+                &["counter"],
+                None,
+                vec!["metric-name-total"],
+            ),
+            // series derived from histograms and summaries keep their suffix
+            ("request_duration_seconds_count", &["counter"], None, vec![]),
+            (
+                "queue_length_gauge",
+                &["gauge"],
+                None,
+                vec!["metric-name-type-suffix"],
+            ),
+            (
+                "up",
+                &["gauge"],
+                Some(&["__meta_pod"]),
+                vec!["label-name-reserved"],
+            ),
+            (
+                "up",
+                &["gauge"],
+                Some(&["exported_job"]),
+                vec!["label-name-reserved"],
+            ),
+            ("up", &["gauge"], Some(&["le"]), vec!["label-name-reserved"]),
+            (
+                "request_duration_seconds_bucket",
+                &["histogram"],
+                Some(&["le"]),
+                vec![],
+            ),
+            (
+                "request_duration_seconds",
+                &["histogram"],
+                Some(&["quantile"]),
+                vec!["label-name-reserved"],
+            ),
+            (
+                "request_duration_seconds",
+                &["summary"],
+                Some(&["quantile"]),
+                vec![],
+            ),
+            (
+                "up",
+                &["gauge"],
+                Some(&["podName"]),
+                vec!["label-name-case"],
+            ),
+        ];
+
+        for (metric, types, labels, expected) in cases {
+            let labels = labels.map(set);
+            let problems = super::check_metric_name(metric, &set(types), labels.as_ref());
+            let rule_ids: Vec<&str> = problems.iter().map(|&(rule_id, _)| rule_id).collect();
+
+            assert_eq!(rule_ids, expected, "{}", metric);
+        }
+    }
+
+    #[test]
+    fn lint_catalog() {
+        let mut snapshot = CatalogSnapshot {
+            version: SNAPSHOT_VERSION,
+            source: "http://prometheus:9090".to_string(),
+            created_at: 0,
+            metrics: BTreeMap::new(),
+        };
+        let mut by_jobs = MetricSnapshot::default();
+        by_jobs
+            .labels
+            .insert("job".to_string(), set(&["node", "api"]));
+        snapshot.metrics.insert("latency_ms".to_string(), by_jobs);
+        snapshot
+            .metrics
+            .insert("dumpLatency".to_string(), MetricSnapshot::default());
+        snapshot
+            .metrics
+            .insert("up".to_string(), MetricSnapshot::default());
+
+        // findings about catalog metrics have a job and a metric, but no pointer or expression
+        let findings: Vec<String> = super::lint_catalog("catalog.json", &snapshot)
+            .into_iter()
+            .map(|finding| {
+                format!(
+                    "{} {:?} {:?} {} {:?} {:?}",
+                    finding.source,
+                    finding.job,
+                    finding.metric,
+                    finding.rule_id,
+                    finding.pointer.len(),
+                    finding.expression
+                )
+            })
+            .collect();
+
+        let expected = vec![
+            r#"catalog.json None Some("dumpLatency") metric-name-case 0 """#,
+            r#"catalog.json Some("api") Some("latency_ms") metric-name-unit 0 """#,
+            r#"catalog.json Some("node") Some("latency_ms") metric-name-unit 0 """#,
+        ];
+        assert_eq!(findings, expected);
+    }
+}
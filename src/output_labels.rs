@@ -0,0 +1,293 @@
+use std::collections::BTreeSet;
+
+use promql::{AggregationAction, Node, Op, OpGroupSide, OpMod, OpModAction};
+
+/// The labels the series of an expression may carry, inferred from its AST.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputLabels {
+    /// A scalar or string, it has no labels.
+    Scalar,
+    /// At most these labels.
+    Only(BTreeSet<String>),
+    /// Any labels of the selected series except these.
+    AllExcept(BTreeSet<String>),
+}
+
+impl OutputLabels {
+    /// Whether the label can never be present.
+    pub fn excludes(&self, label: &str) -> bool {
+        match self {
+            OutputLabels::Scalar => true,
+            OutputLabels::Only(labels) => !labels.contains(label),
+            OutputLabels::AllExcept(labels) => labels.contains(label),
+        }
+    }
+
+    fn union(self, other: OutputLabels) -> OutputLabels {
+        match (self, other) {
+            (OutputLabels::Scalar, other) | (other, OutputLabels::Scalar) => other,
+            (OutputLabels::Only(a), OutputLabels::Only(b)) => {
+                OutputLabels::Only(a.union(&b).cloned().collect())
+            }
+            (OutputLabels::AllExcept(a), OutputLabels::AllExcept(b)) => {
+                OutputLabels::AllExcept(a.intersection(&b).cloned().collect())
+            }
+            (OutputLabels::AllExcept(a), OutputLabels::Only(b))
+            | (OutputLabels::Only(b), OutputLabels::AllExcept(a)) => {
+                OutputLabels::AllExcept(a.difference(&b).cloned().collect())
+            }
+        }
+    }
+
+    /// Keep at most `labels`, like `by` and `on`.
+    fn keep(self, labels: &[String]) -> OutputLabels {
+        let labels = labels.iter().cloned();
+
+        match self {
+            OutputLabels::Scalar => OutputLabels::Scalar,
+            OutputLabels::Only(only) => {
+                OutputLabels::Only(labels.filter(|label| only.contains(label)).collect())
+            }
+            OutputLabels::AllExcept(excluded) => {
+                OutputLabels::Only(labels.filter(|label| !excluded.contains(label)).collect())
+            }
+        }
+    }
+
+    /// Remove `labels`, like `without` and `ignoring`.
+    fn remove(self, labels: &[String]) -> OutputLabels {
+        match self {
+            OutputLabels::Scalar => OutputLabels::Scalar,
+            OutputLabels::Only(only) => OutputLabels::Only(
+                only.into_iter()
+                    .filter(|label| !labels.contains(label))
+                    .collect(),
+            ),
+            OutputLabels::AllExcept(mut excluded) => {
+                excluded.extend(labels.iter().cloned());
+                OutputLabels::AllExcept(excluded)
+            }
+        }
+    }
+
+    /// Add a label, like `label_replace` does.
+    fn add(self, label: String) -> OutputLabels {
+        match self {
+            OutputLabels::Scalar => OutputLabels::Only(vec![label].into_iter().collect()),
+            OutputLabels::Only(mut only) => {
+                only.insert(label);
+                OutputLabels::Only(only)
+            }
+            OutputLabels::AllExcept(mut excluded) => {
+                excluded.remove(&label);
+                OutputLabels::AllExcept(excluded)
+            }
+        }
+    }
+}
+
+static AGGREGATIONS: &[&str] = &[
+    "sum", "avg", "min", "max", "count", "stddev", "stdvar", "group", "quantile", "count_values",
+    "topk", "bottomk", "limitk", "limit_ratio",
+];
+
+/// Aggregations which return input series instead of aggregating them.
+static SELECTING_AGGREGATIONS: &[&str] =
+    &["topk", "bottomk", "limitk", "limit_ratio"];
+
+static SCALAR_FUNCTIONS: &[&str] = &[
+    "scalar", "time", "pi", "minute", "hour", "day_of_month", "day_of_week", "day_of_year",
+    "days_in_month", "month", "year",
+];
+
+/// The labels the series of the expression may carry.
+///
+/// Selectors may carry any label, only aggregations, vector matching and the label functions
+/// narrow them down.
+pub fn get_output_labels(ast: &Node) -> OutputLabels {
+    match ast {
+        Node::Vector(_) => OutputLabels::AllExcept(BTreeSet::new()),
+        Node::Scalar(_) | Node::String(_) => OutputLabels::Scalar,
+        Node::Negation(node) => get_output_labels(node),
+        Node::Operator { x, op, y } => get_operator_labels(x, op, y),
+        Node::Function {
+            name,
+            args,
+            aggregation,
+        } => {
+            let name: &str = name.as_ref();
+
+            if AGGREGATIONS.contains(&name) {
+                // the vector is the last argument, after parameters like the k of topk
+                let input = args
+                    .last()
+                    .map(get_output_labels)
+                    .unwrap_or(OutputLabels::Scalar);
+
+                if SELECTING_AGGREGATIONS.contains(&name) {
+                    return input;
+                }
+
+                let output = match aggregation {
+                    Some(aggregation) if aggregation.action == AggregationAction::By => {
+                        input.keep(&aggregation.labels)
+                    }
+                    Some(aggregation) => input.remove(&aggregation.labels),
+                    None => OutputLabels::Only(BTreeSet::new()),
+                };
+
+                return match (name, args.first()) {
+                    ("count_values", Some(Node::String(label))) => output.add(label.clone()),
+                    _ => output,
+                };
+            }
+
+            if SCALAR_FUNCTIONS.contains(&name) {
+                return OutputLabels::Scalar;
+            }
+
+            match (name, args.get(1)) {
+                ("label_replace", Some(Node::String(label)))
+                | ("label_join", Some(Node::String(label))) => {
+                    return get_output_labels(&args[0]).add(label.clone())
+                }
+                ("vector", _) => return OutputLabels::Only(BTreeSet::new()),
+                ("histogram_quantile", Some(node)) => {
+                    return get_output_labels(node).remove(&["le".to_string()])
+                }
+                _ => (),
+            }
+
+            args.iter()
+                .map(get_output_labels)
+                .fold(OutputLabels::Scalar, OutputLabels::union)
+        }
+    }
+}
+
+fn get_operator_labels(x: &Node, op: &Op, y: &Node) -> OutputLabels {
+    let x = get_output_labels(x);
+    let y = get_output_labels(y);
+
+    let (op_mod, is_set_op) = match op {
+        Op::And(op_mod) | Op::Unless(op_mod) => (op_mod, true),
+        // or keeps the series of both sides with their labels
+        Op::Or(_) => return x.union(y),
+        Op::Pow(op_mod)
+        | Op::Mul(op_mod)
+        | Op::Div(op_mod)
+        | Op::Mod(op_mod)
+        | Op::Plus(op_mod)
+        | Op::Minus(op_mod)
+        | Op::Eq(_, op_mod)
+        | Op::Ne(_, op_mod)
+        | Op::Lt(_, op_mod)
+        | Op::Gt(_, op_mod)
+        | Op::Le(_, op_mod)
+        | Op::Ge(_, op_mod) => (op_mod, false),
+    };
+
+    match (x, y) {
+        (OutputLabels::Scalar, other) | (other, OutputLabels::Scalar) => other,
+        (x, _) if is_set_op => x,
+        (x, y) => match op_mod {
+            Some(OpMod {
+                group: Some(group),
+                ..
+            }) => match group.side {
+                OpGroupSide::Left => x.union(y.keep(&group.labels)),
+                OpGroupSide::Right => y.union(x.keep(&group.labels)),
+            },
+            Some(OpMod {
+                action: OpModAction::RestrictTo,
+                labels,
+                ..
+            }) => x.keep(labels),
+            Some(OpMod {
+                action: OpModAction::Ignore,
+                labels,
+                ..
+            }) => x.remove(labels),
+            None => x,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use promql::parse;
+
+    fn only(labels: &[&str]) -> OutputLabels {
+        OutputLabels::Only(labels.iter().map(|label| label.to_string()).collect())
+    }
+
+    fn all_except(labels: &[&str]) -> OutputLabels {
+        OutputLabels::AllExcept(labels.iter().map(|label| label.to_string()).collect())
+    }
+
+    #[test]
+    fn get_output_labels() {
+        let cases = vec![
+            ("up", all_except(&[])),
+            ("-up offset 5m", all_except(&[])),
+            ("sum(up)", only(&[])),
+            ("sum by (job) (up)", only(&["job"])),
+            ("sum(up) by (job, instance)", only(&["instance", "job"])),
+            ("sum by (job) (up) > bool 0", only(&["job"])),
+            ("sum without (instance) (up)", all_except(&["instance"])),
+            (
+                "max without (instance) (sum without (pod) (up))",
+                all_except(&["instance", "pod"]),
+            ),
+            ("count_values(\"version\", build_info)", only(&["version"])),
+            (
+                "count_values without (version) (\"version\", build_info)",
+                all_except(&[]),
+            ),
+            ("topk(3, sum by (job) (up))", only(&["job"])),
+            (
+                "rate(errors_total[5m]) / on (job) rate(requests_total[5m])",
+                only(&["job"]),
+            ),
+            (
+                "errors_total / ignoring (code) requests_total",
+                all_except(&["code"]),
+            ),
+            (
+                "sum by (job, code) (errors_total) / on (job) group_left (team) team_info",
+                only(&["code", "job", "team"]),
+            ),
+            (
+                "team_info * on (job) group_right sum by (job, code) (errors_total)",
+                only(&["code", "job"]),
+            ),
+            ("up and on (job) sum by (job) (up)", all_except(&[])),
+            ("sum by (job) (up) or vector(0)", only(&["job"])),
+            (
+                "label_replace(sum by (job) (up), \"service\", \"$1\", \"job\", \"(.*)\")",
+                only(&["job", "service"]),
+            ),
+            (
+                "histogram_quantile(0.9, sum by (le, job) (rate(latency_bucket[5m])))",
+                only(&["job"]),
+            ),
+            ("scalar(sum(up))", OutputLabels::Scalar),
+            ("time() - process_start_time_seconds", all_except(&[])),
+        ];
+
+        for (expression, expected) in cases {
+            let ast = parse(expression.as_bytes()).expect("valid expression");
+            assert_eq!(super::get_output_labels(&ast), expected, "{}", expression);
+        }
+    }
+
+    #[test]
+    fn excludes() {
+        assert!(OutputLabels::Scalar.excludes("job"));
+        assert!(only(&["job"]).excludes("instance"));
+        assert!(!only(&["job"]).excludes("job"));
+        assert!(all_except(&["instance"]).excludes("instance"));
+        assert!(!all_except(&["instance"]).excludes("job"));
+    }
+}
@@ -0,0 +1,380 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serializer};
+
+use errors::*;
+use prometheus::Alert;
+use report::{Finding, Severity};
+use usage::*;
+
+/// Conventions every alerting rule has to follow, read from a yaml file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AlertPolicy {
+    /// Values allowed for the `severity` label, which every alert needs.
+    #[serde(default = "default_severities")]
+    pub severities: Vec<String>,
+    /// Severities which page someone, they need a `for` of at least `min_for`.
+    #[serde(default = "default_paging_severities")]
+    pub paging_severities: Vec<String>,
+    #[serde(default = "default_min_for")]
+    pub min_for: String,
+    /// Annotations every alert needs.
+    #[serde(default = "default_required_annotations")]
+    pub required_annotations: Vec<String>,
+    /// Alert names have to match, CamelCase by default. Compiled when the policy is read.
+    #[serde(
+        default = "default_name_pattern",
+        deserialize_with = "deserialize_regex",
+        serialize_with = "serialize_regex"
+    )]
+    pub name_pattern: Regex,
+    #[serde(default = "default_unique_names")]
+    pub unique_names: bool,
+}
+
+fn default_severities() -> Vec<String> {
+    vec!["critical".to_string(), "warning".to_string(), "info".to_string()]
+}
+
+fn default_paging_severities() -> Vec<String> {
+    vec!["critical".to_string()]
+}
+
+fn default_min_for() -> String {
+    "5m".to_string()
+}
+
+fn default_required_annotations() -> Vec<String> {
+    vec!["summary".to_string(), "runbook_url".to_string()]
+}
+
+fn default_name_pattern() -> Regex {
+    Regex::new("^[A-Z][a-zA-Z0-9]*$").unwrap()
+}
+
+fn deserialize_regex<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer)?;
+
+    Regex::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+fn serialize_regex<S>(regex: &Regex, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(regex.as_str())
+}
+
+fn default_unique_names() -> bool {
+    true
+}
+
+impl Default for AlertPolicy {
+    fn default() -> AlertPolicy {
+        AlertPolicy {
+            severities: default_severities(),
+            paging_severities: default_paging_severities(),
+            min_for: default_min_for(),
+            required_annotations: default_required_annotations(),
+            name_pattern: default_name_pattern(),
+            unique_names: default_unique_names(),
+        }
+    }
+}
+
+pub fn load_alert_policy(path: &Path) -> Result<AlertPolicy, CliError> {
+    let content = fs::read(path).map_err(|err| CliError::IoError(path.to_path_buf(), err))?;
+    let policy_error = |message| CliError::AlertPolicyError(path.to_path_buf(), message);
+
+    // an empty file is the default policy
+    let policy: AlertPolicy = if String::from_utf8_lossy(&content).trim().is_empty() {
+        AlertPolicy::default()
+    } else {
+        serde_yaml::from_slice::<Option<AlertPolicy>>(&content)
+            .map_err(|err| policy_error(err.to_string()))?
+            .unwrap_or_default()
+    };
+
+    if parse_duration(&policy.min_for).is_none() {
+        return Err(policy_error(format!("invalid min_for {:?}", policy.min_for)));
+    }
+
+    Ok(policy)
+}
+
+/// Seconds of a prometheus duration like `1h30m`, or of `300s` as the rules api reports it.
+pub fn parse_duration(duration: &str) -> Option<f64> {
+    let mut seconds = 0.0;
+    let mut rest = duration.trim();
+
+    if rest.is_empty() {
+        return None;
+    }
+
+    while !rest.is_empty() {
+        let number_end = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let number: f64 = rest[..number_end].parse().ok()?;
+        rest = &rest[number_end..];
+
+        let unit_end = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let unit = match &rest[..unit_end] {
+            "ms" => 0.001,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            "d" => 86400.0,
+            "w" => 604_800.0,
+            "y" => 31_536_000.0,
+            _ => return None,
+        };
+        rest = &rest[unit_end..];
+
+        seconds += number * unit;
+    }
+
+    Some(seconds)
+}
+
+/// Check an alert against the policy, the messages of all violations by rule id.
+pub fn check_alert(alert: &Alert, policy: &AlertPolicy) -> Vec<(&'static str, String)> {
+    let mut violations = vec![];
+
+    match alert.labels.get("severity") {
+        None => violations.push(("alert-severity", "the severity label is missing".to_string())),
+        Some(severity) if !policy.severities.contains(severity) => violations.push((
+            "alert-severity",
+            format!(
+                "severity {:?} is not one of {}",
+                severity,
+                policy.severities.join(", ")
+            ),
+        )),
+        _ => (),
+    }
+
+    for annotation in policy.required_annotations.iter() {
+        let value = alert
+            .annotations
+            .get(annotation)
+            .filter(|value| !value.trim().is_empty());
+
+        if value.is_none() {
+            violations.push((
+                "alert-annotation",
+                format!("the {} annotation is missing", annotation),
+            ));
+        }
+    }
+
+    if !policy.name_pattern.is_match(&alert.name) {
+        violations.push((
+            "alert-name",
+            format!("the name does not match {:?}", policy.name_pattern.as_str()),
+        ));
+    }
+
+    let is_paging = match alert.labels.get("severity") {
+        Some(severity) => policy.paging_severities.contains(severity),
+        None => false,
+    };
+    let min_for = parse_duration(&policy.min_for).unwrap_or(0.0);
+
+    if is_paging {
+        match alert.for_duration.as_ref() {
+            None => violations.push((
+                "alert-for",
+                format!("paging alerts need a for of at least {}", policy.min_for),
+            )),
+            Some(for_duration) if parse_duration(for_duration).unwrap_or(0.0) < min_for => {
+                violations.push((
+                    "alert-for",
+                    format!(
+                        "for {} is shorter than {} required for paging alerts",
+                        for_duration, policy.min_for
+                    ),
+                ))
+            }
+            _ => (),
+        }
+    }
+
+    violations
+}
+
+/// Findings for the alerting rules violating the policy, including `alert-name-duplicate` for
+/// names defined more than once.
+pub fn check_alert_policy(expressions: &[Expression], policy: &AlertPolicy) -> Vec<Finding> {
+    let alerts: Vec<(&Expression, Alert)> = expressions
+        .iter()
+        .filter_map(|expression| get_alert(expression).map(|alert| (expression, alert)))
+        .collect();
+    let mut findings = vec![];
+
+    let mut groups_by_name: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for (_, alert) in alerts.iter() {
+        groups_by_name
+            .entry(alert.name.as_ref())
+            .or_default()
+            .push(alert.group.clone().unwrap_or_default());
+    }
+
+    for (expression, alert) in alerts.iter() {
+        findings.extend(
+            check_alert(alert, policy)
+                .into_iter()
+                .map(|(rule_id, message)| {
+                    Finding::new(rule_id, Severity::Warning, message, expression)
+                }),
+        );
+
+        let groups = &groups_by_name[alert.name.as_str()];
+        if policy.unique_names && groups.len() > 1 {
+            findings.push(Finding::new(
+                "alert-name-duplicate",
+                Severity::Warning,
+                format!(
+                    "the name is defined {} times, in the groups {}",
+                    groups.len(),
+                    groups.join(", ")
+                ),
+                expression,
+            ));
+        }
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn alert(name: &str, labels: &[(&str, &str)], for_duration: Option<&str>) -> Alert {
+        let to_map = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        };
+
+        Alert {
+            name: name.to_string(),
+            expression: "up == 0".to_string(),
+            labels: to_map(labels),
+            annotations: to_map(&[
+                ("summary", "down"),
+                ("runbook_url", "https://runbooks/down"),
+            ]),
+            for_duration: for_duration.map(|for_duration| for_duration.to_string()),
+            group: None,
+        }
+    }
+
+    #[test]
+    fn check_alert() {
+        let mut no_annotations = alert("TargetDown", &[("severity", "warning")], None);
+        no_annotations.annotations.remove("runbook_url");
+        no_annotations
+            .annotations
+            .insert("summary".to_string(), " ".to_string());
+
+        let cases = vec![
+            (
+                alert("TargetDown", &[("severity", "warning")], None),
+                vec![],
+            ),
+            (alert("TargetDown", &[], None), vec!["alert-severity"]),
+            (
+                alert("TargetDown", &[("severity", "page")], None),
+                vec!["alert-severity"],
+            ),
+            (no_annotations, vec!["alert-annotation", "alert-annotation"]),
+            (
+                alert("target_down", &[("severity", "info")], None),
+                vec!["alert-name"],
+            ),
+            (
+                alert("TargetDown", &[("severity", "critical")], None),
+                vec!["alert-for"],
+            ),
+            (
+                alert("TargetDown", &[("severity", "critical")], Some("1m")),
+                vec!["alert-for"],
+            ),
+            (
+                alert("TargetDown", &[("severity", "critical")], Some("5m")),
+                vec![],
+            ),
+            (
+                alert("TargetDown", &[("severity", "critical")], Some("1h")),
+                vec![],
+            ),
+        ];
+
+        for (alert, expected) in cases {
+            let rule_ids: Vec<&str> = super::check_alert(&alert, &AlertPolicy::default())
+                .into_iter()
+                .map(|(rule_id, _)| rule_id)
+                .collect();
+
+            assert_eq!(rule_ids, expected, "{:?}", alert);
+        }
+    }
+
+    #[test]
+    fn load_alert_policy() {
+        let path = env::temp_dir().join(format!("promqueen-policy-{}.yml", process::id()));
+        // (policy file, name pattern, None for an invalid policy)
+        let cases = vec![
+            ("", Some("^[A-Z][a-zA-Z0-9]*$")),
+            ("severities: [page]", Some("^[A-Z][a-zA-Z0-9]*$")),
+            ("name_pattern: '^[a-z_]+$'", Some("^[a-z_]+$")),
+            ("name_pattern: '^[a-z'", None),
+            ("min_for: soon", None),
+        ];
+
+        for (content, expected) in cases {
+            fs::write(&path, content).unwrap();
+            let policy = super::load_alert_policy(&path).ok();
+
+            assert_eq!(
+                policy.map(|policy| policy.name_pattern.as_str().to_string()),
+                expected.map(|pattern| pattern.to_string()),
+                "{}",
+                content
+            );
+        }
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn parse_duration() {
+        let cases = vec![
+            ("5m", Some(300.0)),
+            ("1h30m", Some(5400.0)),
+            ("300s", Some(300.0)),
+            ("1.5s", Some(1.5)),
+            ("100ms", Some(0.1)),
+            ("", None),
+            ("5", None),
+            ("5 minutes", None),
+        ];
+
+        for (duration, expected) in cases {
+            assert_eq!(super::parse_duration(duration), expected, "{}", duration);
+        }
+    }
+}
@@ -1,23 +1,13 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use restson::{Error, RestClient, RestPath};
+use futures::Future;
 use scraper::{Html, Selector};
 
+use api_client::{Api, ApiClient, ApiFuture, ClientBuilder, ClientOptions, FromApiClient};
 use errors::ClientError;
-
-use http_client::HttpClient;
-
-#[derive(Serialize, Deserialize, Debug)]
-struct GetLabelValues {
-    data: Vec<String>,
-    status: String,
-}
-
-impl RestPath<String> for GetLabelValues {
-    fn get_path(label: String) -> Result<String, Error> {
-        Ok(format!("api/v1/label/{}/values", label))
-    }
-}
+use rules::{Rule, RuleFile, RuleGroup};
+use runtime;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Alert {
@@ -27,59 +17,232 @@ pub struct Alert {
     pub expression: String,
     pub labels: HashMap<String, String>,
     pub annotations: HashMap<String, String>,
+    #[serde(rename = "for", default, skip_serializing_if = "Option::is_none")]
+    pub for_duration: Option<String>,
+    /// The rule group, unknown for alerts read from the alerts page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+/// Metadata of a metric as reported by its targets.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MetricMetadata {
+    #[serde(rename = "type")]
+    pub metric_type: String,
+    pub help: String,
+    #[serde(default)]
+    pub unit: String,
+}
+
+/// A rule group as reported by `/api/v1/rules`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiRuleGroup {
+    pub name: String,
+    pub file: String,
+    /// Evaluation interval in seconds.
+    #[serde(default)]
+    pub interval: Option<f64>,
+    #[serde(default = "Vec::new")]
+    pub rules: Vec<ApiRule>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiRule {
+    pub name: String,
+    pub query: String,
+    /// `alerting` or `recording`.
+    #[serde(rename = "type")]
+    pub rule_type: String,
+    /// The `for` duration of alerting rules in seconds.
+    #[serde(default)]
+    pub duration: f64,
+    #[serde(default = "HashMap::new")]
+    pub labels: HashMap<String, String>,
+    #[serde(default = "HashMap::new")]
+    pub annotations: HashMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct RulesData {
+    groups: Vec<ApiRuleGroup>,
 }
 
+/// The labels of a single series.
+pub type Series = HashMap<String, String>;
+
+/// Series lookups only cover the last hour by default, prometheus scans its whole retention
+/// without a time range.
+pub const DEFAULT_SERIES_WINDOW_SECS: u64 = 3600;
+
+/// Prometheus http api client, clones share connections.
+///
+/// The blocking methods panic on the threads of `runtime::executor()`, futures running there use
+/// the `fetch_*` methods.
+#[derive(Clone)]
 pub struct PrometheusClient {
-    rest_client: RestClient,
-    http_client: HttpClient,
+    client: ApiClient,
+    series_window: Duration,
 }
 
-fn to_prometheus_error(err: Error) -> ClientError {
-    ClientError::RestError {
-        client: "prometheus",
-        err: err,
+impl FromApiClient for PrometheusClient {
+    const API: Api = Api::Prometheus;
+
+    fn from_api_client(client: ApiClient) -> PrometheusClient {
+        PrometheusClient {
+            client,
+            series_window: Duration::from_secs(DEFAULT_SERIES_WINDOW_SECS),
+        }
     }
 }
 
 impl PrometheusClient {
-    pub fn new(url: String) -> Result<PrometheusClient, ClientError> {
-        RestClient::new(&url)
-            .and_then(|mut rest_client| {
-                rest_client.set_header("Accept", "application/json")?;
-                rest_client.set_header("Content-Type", "application/json")?;
-
-                HttpClient::new(url, None, None).map(|http_client| PrometheusClient {
-                    http_client,
-                    rest_client,
-                })
-            }).map_err(to_prometheus_error)
-    }
-
-    pub fn get_label_values(mut self, label: String) -> Result<Vec<String>, ClientError> {
-        let result: Result<GetLabelValues, Error> = self.rest_client.get(label);
-        result
-            .map(|result| result.data)
-            .map_err(to_prometheus_error)
-    }
-
-    pub fn get_alerts(mut self) -> Result<Vec<Alert>, ClientError> {
-        let alerts_page = self
-            .http_client
-            .get("alerts", None, None)
-            .map(|response| response.body().to_owned())
-            .map_err(to_prometheus_error)?;
-
-        let document = Html::parse_document(&alerts_page);
-        let selector = Selector::parse("code").unwrap();
-        let code_elements = document
-            .select(&selector)
-            .map(|element| {
-                let text = element.text().collect::<Vec<&str>>().join("\n");
-                serde_yaml::from_str::<Alert>(&text)
-            }).filter(|alert| alert.is_ok())
-            .map(|may_alert| may_alert.unwrap())
-            .collect::<Vec<Alert>>();
-
-        Ok(code_elements)
+    pub fn new(url: String, options: &ClientOptions) -> Result<PrometheusClient, ClientError> {
+        PrometheusClient::builder(url)
+            .options(options.clone())
+            .build()
+    }
+
+    pub fn builder<U: Into<String>>(url: U) -> ClientBuilder<PrometheusClient> {
+        ClientBuilder::new(url)
+    }
+
+    /// Look up series that existed during the last `series_window` only.
+    pub fn with_series_window(self, series_window: Duration) -> PrometheusClient {
+        PrometheusClient {
+            series_window,
+            ..self
+        }
+    }
+
+    pub fn series_window(&self) -> Duration {
+        self.series_window
+    }
+
+    pub fn get_label_values(&self, label: String) -> Result<Vec<String>, ClientError> {
+        runtime::block_on(self.fetch_label_values(&label))
     }
+
+    pub fn get_alerts(&self) -> Result<Vec<Alert>, ClientError> {
+        runtime::block_on(self.fetch_alerts())
+    }
+
+    /// The loaded rule groups, requires prometheus v2.2.
+    pub fn get_rules(&self) -> Result<Vec<ApiRuleGroup>, ClientError> {
+        runtime::block_on(self.fetch_rules())
+    }
+
+    /// The loaded rules in rule file format, groups of all files are merged.
+    pub fn get_rule_file(&self) -> Result<RuleFile, ClientError> {
+        self.get_rules().map(to_rule_file)
+    }
+
+    /// Metadata by metric name, requires prometheus v2.15.
+    pub fn get_metadata(&self) -> Result<BTreeMap<String, Vec<MetricMetadata>>, ClientError> {
+        runtime::block_on(self.fetch_metadata())
+    }
+
+    /// Look up the series of every matcher with at most `concurrency` requests in flight,
+    /// the results are in the order of `matchers`.
+    pub fn get_series(
+        &self,
+        matchers: Vec<String>,
+        concurrency: usize,
+    ) -> Result<Vec<Vec<Series>>, ClientError> {
+        let client = self.clone();
+
+        runtime::block_on(runtime::map_concurrent(matchers, concurrency, move |matcher| {
+            client.fetch_series(&matcher)
+        }))
+    }
+
+    pub fn fetch_label_values(&self, label: &str) -> ApiFuture<Vec<String>> {
+        self.client
+            .get_data(&format!("api/v1/label/{}/values", label), None)
+    }
+
+    pub fn fetch_metadata(&self) -> ApiFuture<BTreeMap<String, Vec<MetricMetadata>>> {
+        self.client.get_data("api/v1/metadata", None)
+    }
+
+    pub fn fetch_series(&self, matcher: &str) -> ApiFuture<Vec<Series>> {
+        let (start, end) = series_range(SystemTime::now(), self.series_window);
+
+        self.client.get_data(
+            "api/v1/series",
+            Some(&[("match[]", matcher), ("start", &start), ("end", &end)]),
+        )
+    }
+
+    pub fn fetch_rules(&self) -> ApiFuture<Vec<ApiRuleGroup>> {
+        Box::new(
+            self.client
+                .get_data::<RulesData>("api/v1/rules", None)
+                .map(|data| data.groups),
+        )
+    }
+
+    pub fn fetch_alerts(&self) -> ApiFuture<Vec<Alert>> {
+        Box::new(
+            self.client
+                .get_text("alerts", None)
+                .map(|alerts_page| parse_alerts_page(&alerts_page)),
+        )
+    }
+}
+
+/// `start` and `end` of the series window ending at `now`, as unix timestamps.
+fn series_range(now: SystemTime, window: Duration) -> (String, String) {
+    let end = now
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs())
+        .unwrap_or(0);
+
+    (
+        end.saturating_sub(window.as_secs()).to_string(),
+        end.to_string(),
+    )
+}
+
+fn to_rule_file(groups: Vec<ApiRuleGroup>) -> RuleFile {
+    RuleFile {
+        groups: groups
+            .into_iter()
+            .map(|group| RuleGroup {
+                name: group.name,
+                interval: group.interval.map(|interval| format!("{}s", interval)),
+                rules: group
+                    .rules
+                    .into_iter()
+                    .map(|rule| {
+                        let alerting = rule.rule_type == "alerting";
+
+                        Rule {
+                            alert: if alerting { Some(rule.name.clone()) } else { None },
+                            record: if alerting { None } else { Some(rule.name) },
+                            expr: rule.query,
+                            for_duration: if alerting && rule.duration > 0.0 {
+                                Some(format!("{}s", rule.duration))
+                            } else {
+                                None
+                            },
+                            labels: rule.labels,
+                            annotations: rule.annotations,
+                        }
+                    }).collect(),
+            }).collect(),
+    }
+}
+
+fn parse_alerts_page(alerts_page: &str) -> Vec<Alert> {
+    let document = Html::parse_document(alerts_page);
+    let selector = Selector::parse("code").unwrap();
+
+    document
+        .select(&selector)
+        .map(|element| {
+            let text = element.text().collect::<Vec<&str>>().join("\n");
+            serde_yaml::from_str::<Alert>(&text)
+        }).filter(|alert| alert.is_ok())
+        .map(|may_alert| may_alert.unwrap())
+        .collect::<Vec<Alert>>()
 }
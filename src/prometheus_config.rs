@@ -0,0 +1,208 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use promql::{parse, LabelMatchOp};
+use regex::Regex;
+
+use errors::*;
+use policy::parse_duration;
+use relabel::RelabelConfig;
+use report::{Finding, Severity};
+use usage::*;
+
+lazy_static! {
+    /// A grafana template variable like `$job`, `${job}`, `${job:regex}` or `[[job]]`.
+    static ref GRAFANA_VARIABLE: Regex =
+        Regex::new(r"\$\w+|\$\{[^}]+\}|\[\[[^\]]+\]\]").unwrap();
+}
+
+/// The evaluation interval of rule groups without one if the config does not set it.
+pub const DEFAULT_EVALUATION_INTERVAL_SECS: f64 = 60.0;
+
+/// The parts of `prometheus.yml` promqueen uses.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PrometheusConfig {
+    #[serde(default)]
+    pub global: GlobalConfig,
+    /// Globs of rule files, relative to the directory of the config.
+    #[serde(default = "Vec::new")]
+    pub rule_files: Vec<String>,
+    #[serde(default = "Vec::new")]
+    pub scrape_configs: Vec<ScrapeConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct GlobalConfig {
+    pub evaluation_interval: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScrapeConfig {
+    pub job_name: String,
+    /// Applied to the labels of targets before scraping.
+    #[serde(default = "Vec::new")]
+    pub relabel_configs: Vec<RelabelConfig>,
+    /// Applied to the scraped samples before ingestion.
+    #[serde(default = "Vec::new")]
+    pub metric_relabel_configs: Vec<RelabelConfig>,
+}
+
+impl PrometheusConfig {
+    pub fn job_names(&self) -> BTreeSet<String> {
+        self.scrape_configs
+            .iter()
+            .map(|scrape_config| scrape_config.job_name.clone())
+            .collect()
+    }
+
+    pub fn get_scrape_config(&self, job_name: &str) -> Option<&ScrapeConfig> {
+        self.scrape_configs
+            .iter()
+            .find(|scrape_config| scrape_config.job_name == job_name)
+    }
+
+    /// Seconds of `global.evaluation_interval`, one minute if it is not set.
+    pub fn evaluation_interval(&self) -> Result<f64, String> {
+        match self.global.evaluation_interval {
+            Some(ref interval) => parse_duration(interval)
+                .ok_or_else(|| format!("invalid global.evaluation_interval {:?}", interval)),
+            None => Ok(DEFAULT_EVALUATION_INTERVAL_SECS),
+        }
+    }
+}
+
+pub fn load_prometheus_config(path: &Path) -> Result<PrometheusConfig, CliError> {
+    let content = fs::read(path).map_err(|err| CliError::IoError(path.to_path_buf(), err))?;
+
+    serde_yaml::from_slice(&content)
+        .map_err(|err| CliError::PrometheusConfigError(path.to_path_buf(), err.to_string()))
+}
+
+/// The files matched by the `rule_files` globs of the config at `path`, sorted.
+pub fn get_rule_file_paths(
+    path: &Path,
+    config: &PrometheusConfig,
+) -> Result<Vec<PathBuf>, CliError> {
+    let config_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut paths = BTreeSet::new();
+
+    for pattern in config.rule_files.iter() {
+        let pattern = config_dir.join(pattern);
+        let matches = glob::glob(&pattern.to_string_lossy()).map_err(|err| {
+            CliError::PrometheusConfigError(path.to_path_buf(), err.to_string())
+        })?;
+
+        for rule_file in matches {
+            let rule_file = rule_file
+                .map_err(|err| CliError::IoError(err.path().to_path_buf(), err.into()))?;
+            paths.insert(rule_file);
+        }
+    }
+
+    Ok(paths.into_iter().collect())
+}
+
+/// `unknown-job` findings for `job="..."` matchers of jobs missing in the scrape configs.
+///
+/// Values with grafana template variables are skipped. Jobs renamed by `relabel_configs` with
+/// `target_label: job` are not known, matchers of them are reported as well.
+pub fn find_unknown_jobs(expressions: &[Expression], jobs: &BTreeSet<String>) -> Vec<Finding> {
+    expressions
+        .iter()
+        .filter_map(|expression| {
+            let ast = parse(expression.expression.as_bytes()).ok()?;
+            let mut vectors = vec![];
+            get_vectors_from_node(&ast, &mut vectors);
+
+            let unknown: BTreeSet<&String> = vectors
+                .iter()
+                .flat_map(|vector| vector.labels.iter())
+                .filter(|label| label.name == "job" && label.op == LabelMatchOp::Eq)
+                .map(|label| &label.value)
+                .filter(|job| !jobs.contains(*job) && !GRAFANA_VARIABLE.is_match(job))
+                .collect();
+
+            if unknown.is_empty() {
+                return None;
+            }
+
+            Some(Finding::new(
+                "unknown-job",
+                Severity::Warning,
+                format!(
+                    "jobs missing in the scrape configs: {}",
+                    unknown
+                        .into_iter()
+                        .map(|job| job.as_ref())
+                        .collect::<Vec<&str>>()
+                        .join(", ")
+                ),
+                expression,
+            ))
+        }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn get_rule_file_paths() {
+        let dir = env::temp_dir().join(format!("promqueen-rule-files-{}", process::id()));
+        for file in &[
+            "rules/a.yml",
+            "rules/b.yml",
+            "rules/notes.txt",
+            "other/c.yml",
+            "d.yml",
+        ] {
+            let path = dir.join("config").join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "groups: []").unwrap();
+        }
+        let config_path = dir.join("config").join("prometheus.yml");
+        let absolute = dir.join("config/other/*.yml").to_string_lossy().to_string();
+
+        // (rule_files, expected paths relative to the config directory)
+        let cases = vec![
+            (vec!["rules/*.yml"], vec!["rules/a.yml", "rules/b.yml"]),
+            (vec!["d.yml"], vec!["d.yml"]),
+            (
+                vec!["rules/*", "rules/a.yml"],
+                vec!["rules/a.yml", "rules/b.yml", "rules/notes.txt"],
+            ),
+            (
+                vec!["*/*.yml", "d.yml"],
+                vec!["d.yml", "other/c.yml", "rules/a.yml", "rules/b.yml"],
+            ),
+            (vec![absolute.as_str()], vec!["other/c.yml"]),
+            // globs without matches are not an error, like in prometheus
+            (vec!["missing/*.yml"], vec![]),
+        ];
+
+        for (rule_files, expected) in cases {
+            let config = PrometheusConfig {
+                rule_files: rule_files.iter().map(|file| file.to_string()).collect(),
+                ..PrometheusConfig::default()
+            };
+            let expected: Vec<PathBuf> = expected
+                .iter()
+                .map(|file| dir.join("config").join(file))
+                .collect();
+
+            let paths = super::get_rule_file_paths(&config_path, &config).expect("valid globs");
+            assert_eq!(paths, expected, "{:?}", rule_files);
+        }
+
+        let config = PrometheusConfig {
+            rule_files: vec!["rules/[.yml".to_string()],
+            ..PrometheusConfig::default()
+        };
+        assert!(super::get_rule_file_paths(&config_path, &config).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
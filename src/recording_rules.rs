@@ -0,0 +1,339 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use catalog::MetricCatalog;
+use policy::parse_duration;
+use report::{Finding, Severity};
+use usage::*;
+
+/// A recording rule expression with the metrics it reads.
+#[derive(Debug, Clone)]
+pub struct RecordingRule<'a> {
+    /// The recorded metric.
+    pub name: String,
+    pub expression: &'a Expression,
+    /// Position among all expressions, rules are evaluated in this order.
+    pub index: usize,
+    /// Source, id and name of the group.
+    pub group: (String, u32, String),
+    /// Evaluation interval in seconds, `None` for the global one.
+    pub interval: Option<f64>,
+    /// Metrics used by the expression, empty if it can not be parsed.
+    pub inputs: BTreeSet<String>,
+}
+
+/// Every recording rule among the expressions.
+pub fn get_recording_rules<'a>(expressions: &'a [Expression]) -> Vec<RecordingRule<'a>> {
+    expressions
+        .iter()
+        .enumerate()
+        .filter_map(|(index, expression)| {
+            let rule = expression.pointer.last()?;
+            if rule.pointer_type != "record" {
+                return None;
+            }
+
+            let group = expression
+                .pointer
+                .iter()
+                .rev()
+                .find(|pointer| pointer.pointer_type == "group")
+                .map(|group| (group.id, group.title.clone().unwrap_or_default()))
+                .unwrap_or_default();
+
+            Some(RecordingRule {
+                name: rule.title.clone().unwrap_or_default(),
+                expression,
+                index,
+                group: (expression.context.source.clone(), group.0, group.1),
+                interval: expression
+                    .context
+                    .interval
+                    .as_ref()
+                    .and_then(|interval| parse_duration(interval)),
+                inputs: get_used_metrics_from_expression(&expression.expression)
+                    .map(|metrics| metrics.into_iter().collect())
+                    .unwrap_or_default(),
+            })
+        }).collect()
+}
+
+/// The rules recording each metric, a metric may be recorded by several rules.
+pub fn get_definitions<'a, 'b>(
+    rules: &'b [RecordingRule<'a>],
+) -> BTreeMap<&'b str, Vec<&'b RecordingRule<'a>>> {
+    let mut definitions: BTreeMap<&str, Vec<&RecordingRule>> = BTreeMap::new();
+
+    for rule in rules.iter() {
+        definitions.entry(rule.name.as_ref()).or_default().push(rule);
+    }
+
+    definitions
+}
+
+/// The metrics and every input of the recorded ones among them, transitively.
+pub fn expand_recorded_metrics(
+    metrics: BTreeSet<String>,
+    definitions: &BTreeMap<&str, Vec<&RecordingRule>>,
+) -> BTreeSet<String> {
+    let mut expanded = BTreeSet::new();
+    let mut queue: VecDeque<String> = metrics.into_iter().collect();
+
+    while let Some(metric) = queue.pop_front() {
+        if expanded.contains(&metric) {
+            continue;
+        }
+
+        for rule in definitions.get(metric.as_str()).into_iter().flatten() {
+            queue.extend(rule.inputs.iter().cloned());
+        }
+        expanded.insert(metric);
+    }
+
+    expanded
+}
+
+/// Whether the name follows `level:metric:operations`.
+pub fn is_valid_record_name(name: &str) -> bool {
+    let parts: Vec<&str> = name.split(':').collect();
+
+    parts.len() == 3
+        && parts.iter().all(|part| {
+            !part.is_empty()
+                && !part.starts_with(|c: char| c.is_ascii_digit())
+                && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+}
+
+/// A path of recording rules from the rule back to itself, if it depends on itself.
+fn find_cycle(
+    rule: &RecordingRule,
+    definitions: &BTreeMap<&str, Vec<&RecordingRule>>,
+) -> Option<Vec<String>> {
+    let mut parents: BTreeMap<String, String> = BTreeMap::new();
+    let mut queue: VecDeque<String> = VecDeque::new();
+
+    for input in rule.inputs.iter() {
+        if !parents.contains_key(input) {
+            parents.insert(input.clone(), rule.name.clone());
+            queue.push_back(input.clone());
+        }
+    }
+
+    while let Some(metric) = queue.pop_front() {
+        if metric == rule.name {
+            let mut path = vec![metric.clone()];
+            let mut current = parents[&metric].clone();
+            while current != rule.name {
+                path.push(current.clone());
+                current = parents[&current].clone();
+            }
+            path.push(rule.name.clone());
+            path.reverse();
+
+            return Some(path);
+        }
+
+        for definition in definitions.get(metric.as_str()).into_iter().flatten() {
+            for input in definition.inputs.iter() {
+                if !parents.contains_key(input) {
+                    parents.insert(input.clone(), metric.clone());
+                    queue.push_back(input.clone());
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Findings for the recording rules among the expressions:
+/// * `record-name`: names not following `level:metric:operations`
+/// * `record-cycle`: rules depending on themselves
+/// * `record-order`: rules using a metric recorded later by a group with another interval, groups
+///   without interval are evaluated every `default_interval` seconds
+/// * `record-unused`: recorded metrics no dashboard or alert uses, directly or through other rules
+/// * `record-missing-input`: inputs missing in the catalog and not recorded by any rule
+pub fn check_recording_rules(
+    expressions: &[Expression],
+    catalog: &MetricCatalog,
+    default_interval: f64,
+) -> Vec<Finding> {
+    let rules = get_recording_rules(expressions);
+    let definitions = get_definitions(&rules);
+    let mut findings = vec![];
+
+    let consumed: BTreeSet<String> = expressions
+        .iter()
+        .filter(|expression| {
+            expression.pointer.last().map(|pointer| pointer.pointer_type.as_ref()) != Some("record")
+        }).filter_map(|expression| get_used_metrics_from_expression(&expression.expression).ok())
+        .flatten()
+        .collect();
+    let used = expand_recorded_metrics(consumed, &definitions);
+
+    for rule in rules.iter() {
+        let expression = rule.expression;
+
+        if !is_valid_record_name(&rule.name) {
+            findings.push(Finding::new(
+                "record-name",
+                Severity::Warning,
+                "the name does not follow level:metric:operations".to_string(),
+                expression,
+            ));
+        }
+
+        if let Some(cycle) = find_cycle(rule, &definitions) {
+            findings.push(Finding::new(
+                "record-cycle",
+                Severity::Error,
+                format!("the rule depends on itself: {}", cycle.join(" -> ")),
+                expression,
+            ));
+        }
+
+        for input in rule.inputs.iter() {
+            let input_rules = match definitions.get(input.as_str()) {
+                Some(input_rules) => input_rules,
+                None => {
+                    if !catalog.has_metric(input) {
+                        let mut finding = Finding::new(
+                            "record-missing-input",
+                            Severity::Error,
+                            format!("the input {} is neither in the catalog nor recorded", input),
+                            expression,
+                        );
+                        finding.metrics = vec![input.clone()];
+                        findings.push(finding);
+                    }
+                    continue;
+                }
+            };
+
+            let interval = rule.interval.unwrap_or(default_interval);
+            let later = input_rules.iter().find(|input_rule| {
+                input_rule.index > rule.index
+                    && input_rule.group != rule.group
+                    && input_rule.interval.unwrap_or(default_interval) != interval
+            });
+            if let Some(input_rule) = later {
+                findings.push(Finding::new(
+                    "record-order",
+                    Severity::Warning,
+                    format!(
+                        "the input {} is recorded later by group {} with interval {}",
+                        input,
+                        input_rule.group.2,
+                        input_rule
+                            .expression
+                            .context
+                            .interval
+                            .clone()
+                            .unwrap_or_else(|| "global".to_string())
+                    ),
+                    expression,
+                ));
+            }
+        }
+
+        if !used.contains(&rule.name) {
+            findings.push(Finding::new(
+                "record-unused",
+                Severity::Warning,
+                "no dashboard or alert uses the recorded metric".to_string(),
+                expression,
+            ));
+        }
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus_config::PrometheusConfig;
+    use rules::RuleFile;
+    use snapshot::{CatalogSnapshot, SNAPSHOT_VERSION};
+
+    fn rule_file(interval_a: Option<&str>, interval_b: Option<&str>) -> RuleFile {
+        let interval = |interval: Option<&str>| {
+            interval
+                .map(|interval| format!("  interval: {}\n", interval))
+                .unwrap_or_default()
+        };
+
+        // job:x:sum of group a reads job:y:sum, which group b records after it
+        serde_yaml::from_str(&format!(
+            "groups:
+- name: a
+{}  rules:
+  - record: job:x:sum
+    expr: sum by (job) (job:y:sum)
+- name: b
+{}  rules:
+  - record: job:y:sum
+    expr: sum by (job) (up)
+",
+            interval(interval_a),
+            interval(interval_b)
+        )).expect("valid rule file")
+    }
+
+    #[test]
+    fn record_order_uses_the_global_interval() {
+        let catalog = CatalogSnapshot {
+            version: SNAPSHOT_VERSION,
+            source: "catalog.json".to_string(),
+            created_at: 0,
+            metrics: vec![("up".to_string(), Default::default())]
+                .into_iter()
+                .collect(),
+        };
+        // (global config, interval of group a, interval of group b, record-order expected)
+        let cases = vec![
+            // one minute if the config does not set it
+            ("global: {}", None, None, false),
+            ("global: {}", None, Some("1m"), false),
+            ("global: {}", None, Some("30s"), true),
+            (
+                "global: {evaluation_interval: 30s}",
+                None,
+                Some("30s"),
+                false,
+            ),
+            ("global: {evaluation_interval: 30s}", None, Some("1m"), true),
+            (
+                "global: {evaluation_interval: 30s}",
+                Some("30s"),
+                None,
+                false,
+            ),
+            ("global: {evaluation_interval: 1m}", Some("30s"), None, true),
+            (
+                "global: {evaluation_interval: 30s}",
+                Some("1m"),
+                Some("2m"),
+                true,
+            ),
+            (
+                "global: {evaluation_interval: 30s}",
+                Some("2m"),
+                Some("120s"),
+                false,
+            ),
+        ];
+
+        for case in cases {
+            let (global, interval_a, interval_b, expected) = case;
+            let config: PrometheusConfig = serde_yaml::from_str(global).expect("valid config");
+            let default_interval = config.evaluation_interval().expect("valid interval");
+            let expressions = get_expressions_from_rule_file(&rule_file(interval_a, interval_b));
+
+            let record_order = check_recording_rules(&expressions, &catalog, default_interval)
+                .iter()
+                .any(|finding| finding.rule_id == "record-order");
+            assert_eq!(record_order, expected, "{:?}", case);
+        }
+    }
+}
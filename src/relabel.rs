@@ -0,0 +1,485 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use promql::{parse, Node};
+use regex::Regex;
+
+use prometheus::Series;
+use prometheus_config::PrometheusConfig;
+use report::{Finding, Severity};
+use templates::{get_legend_label_refs, get_template_label_refs};
+use usage::*;
+
+/// A rule of `relabel_configs` or `metric_relabel_configs`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RelabelConfig {
+    #[serde(default = "Vec::new")]
+    pub source_labels: Vec<String>,
+    #[serde(default = "default_separator")]
+    pub separator: String,
+    #[serde(default = "default_regex")]
+    pub regex: String,
+    #[serde(default)]
+    pub modulus: u64,
+    pub target_label: Option<String>,
+    #[serde(default = "default_replacement")]
+    pub replacement: String,
+    #[serde(default = "default_action")]
+    pub action: String,
+}
+
+fn default_separator() -> String {
+    ";".to_string()
+}
+
+fn default_regex() -> String {
+    "(.*)".to_string()
+}
+
+fn default_replacement() -> String {
+    "$1".to_string()
+}
+
+fn default_action() -> String {
+    "replace".to_string()
+}
+
+pub type Labels = BTreeMap<String, String>;
+
+/// Compiled relabel rules, applied in order like prometheus does.
+pub struct Relabeler {
+    rules: Vec<(RelabelConfig, Regex)>,
+}
+
+impl Relabeler {
+    pub fn new(configs: &[RelabelConfig]) -> Result<Relabeler, String> {
+        let rules = configs
+            .iter()
+            .map(|config| {
+                // prometheus anchors relabel regexes at both ends
+                Regex::new(&format!("^(?:{})$", config.regex))
+                    .map(|regex| (config.clone(), regex))
+                    .map_err(|err| format!("invalid relabel regex {:?}: {}", config.regex, err))
+            }).collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Relabeler { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The labels after relabeling, `None` if the series is dropped.
+    pub fn apply(&self, labels: &Labels) -> Option<Labels> {
+        let mut labels = labels.clone();
+
+        for (config, regex) in self.rules.iter() {
+            let value = config
+                .source_labels
+                .iter()
+                .map(|name| labels.get(name).map(|value| value.as_ref()).unwrap_or(""))
+                .collect::<Vec<&str>>()
+                .join(&config.separator);
+            let target = config.target_label.clone().unwrap_or_default();
+
+            match config.action.as_ref() {
+                "keep" if !regex.is_match(&value) => return None,
+                "drop" if regex.is_match(&value) => return None,
+                // a missing target label compares as empty, like in prometheus
+                "keepequal" if target_value(&labels, &target) != value => return None,
+                "dropequal" if target_value(&labels, &target) == value => return None,
+                "replace" => {
+                    if let Some(captures) = regex.captures(&value) {
+                        let mut target_name = String::new();
+                        captures.expand(&target, &mut target_name);
+                        let mut replacement = String::new();
+                        captures.expand(&config.replacement, &mut replacement);
+
+                        if replacement.is_empty() {
+                            labels.remove(&target_name);
+                        } else if !target_name.is_empty() {
+                            labels.insert(target_name, replacement);
+                        }
+                    }
+                }
+                "lowercase" => {
+                    labels.insert(target, value.to_lowercase());
+                }
+                "uppercase" => {
+                    labels.insert(target, value.to_uppercase());
+                }
+                // the hash is not predictable, but the label exists afterwards
+                "hashmod" => {
+                    labels.insert(target, "0".to_string());
+                }
+                "labelmap" => {
+                    let mapped: Vec<(String, String)> = labels
+                        .iter()
+                        .filter_map(|(name, value)| {
+                            regex.captures(name).map(|captures| {
+                                let mut mapped_name = String::new();
+                                captures.expand(&config.replacement, &mut mapped_name);
+                                (mapped_name, value.clone())
+                            })
+                        }).collect();
+                    labels.extend(mapped);
+                }
+                "labeldrop" => labels.retain(|name, _| !regex.is_match(name)),
+                "labelkeep" => labels.retain(|name, _| regex.is_match(name)),
+                _ => (),
+            }
+        }
+
+        // labels starting with __ are removed after relabeling, except the metric name
+        labels.retain(|name, _| !name.starts_with("__") || name == "__name__");
+
+        Some(labels)
+    }
+}
+
+fn target_value<'a>(labels: &'a Labels, target: &str) -> &'a str {
+    labels.get(target).map(|value| value.as_str()).unwrap_or("")
+}
+
+/// The relabel rules to predict per job: all rules of `config`, or with `old_config` only the
+/// rules missing in the same job of `old_config`.
+///
+/// The series already passed the rules prometheus runs with, so only the rules asked about are
+/// applied to their labels; rules of the old version are not applied a second time. Series only
+/// carry the labels left after target relabeling, so `relabel_configs` rules reading internal
+/// labels like `__meta_*` or `__address__` are skipped.
+pub fn get_relabelers(
+    config: &PrometheusConfig,
+    old_config: Option<&PrometheusConfig>,
+) -> Result<BTreeMap<String, Relabeler>, String> {
+    let mut relabelers = BTreeMap::new();
+
+    for scrape_config in config.scrape_configs.iter() {
+        let old_scrape_config =
+            old_config.and_then(|old_config| old_config.get_scrape_config(&scrape_config.job_name));
+        let is_new = |rule: &&RelabelConfig, old_rules: Option<&Vec<RelabelConfig>>| {
+            old_rules.map_or(true, |old_rules| !old_rules.contains(rule))
+        };
+
+        let target_rules = scrape_config.relabel_configs.iter().filter(|rule| {
+            is_new(rule, old_scrape_config.map(|old| &old.relabel_configs))
+                && !rule
+                    .source_labels
+                    .iter()
+                    .any(|name| name.starts_with("__") && name != "__name__")
+        });
+        let metric_rules = scrape_config.metric_relabel_configs.iter().filter(|rule| {
+            is_new(
+                rule,
+                old_scrape_config.map(|old| &old.metric_relabel_configs),
+            )
+        });
+
+        let rules: Vec<RelabelConfig> = target_rules.chain(metric_rules).cloned().collect();
+        let relabeler = Relabeler::new(&rules)
+            .map_err(|err| format!("job {}: {}", scrape_config.job_name, err))?;
+
+        if !relabeler.is_empty() {
+            relabelers.insert(scrape_config.job_name.clone(), relabeler);
+        }
+    }
+
+    Ok(relabelers)
+}
+
+/// Metrics and labels which relabeling would remove.
+#[derive(Serialize, Debug, Default)]
+pub struct Prediction {
+    pub dropped_metrics: BTreeSet<String>,
+    /// Labels missing on every remaining series, by metric.
+    pub dropped_labels: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// Relabel the series of every metric with the relabeler of their `job`.
+pub fn predict(
+    series_by_metric: &BTreeMap<String, Vec<Series>>,
+    relabelers: &BTreeMap<String, Relabeler>,
+) -> Prediction {
+    let mut prediction = Prediction::default();
+
+    for (metric, series) in series_by_metric.iter() {
+        let mut labels_before: BTreeSet<String> = BTreeSet::new();
+        let mut labels_after: BTreeSet<String> = BTreeSet::new();
+        let mut remaining = 0;
+
+        for labels in series.iter() {
+            let labels: Labels = labels
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect();
+            labels_before.extend(labels.keys().cloned());
+
+            let relabeled = match labels.get("job").and_then(|job| relabelers.get(job)) {
+                Some(relabeler) => relabeler.apply(&labels),
+                None => Some(labels),
+            };
+
+            // a renamed series is lost for the metric as well
+            if let Some(relabeled) = relabeled {
+                if relabeled.get("__name__") == Some(metric) {
+                    remaining += 1;
+                    labels_after.extend(relabeled.keys().cloned());
+                }
+            }
+        }
+
+        if series.is_empty() {
+            continue;
+        }
+
+        if remaining == 0 {
+            prediction.dropped_metrics.insert(metric.clone());
+            continue;
+        }
+
+        let dropped: BTreeSet<String> = labels_before.difference(&labels_after).cloned().collect();
+        if !dropped.is_empty() {
+            prediction.dropped_labels.insert(metric.clone(), dropped);
+        }
+    }
+
+    prediction
+}
+
+/// The labels an expression refers to apart from its matchers: grouping and vector matching
+/// labels, placeholders of the legend format and labels of the annotation templates.
+fn get_referenced_labels(ast: &Node, expression: &Expression) -> BTreeSet<String> {
+    let mut labels = BTreeSet::new();
+    get_grouping_labels_from_node(ast, &mut labels);
+
+    if let Some(ref legend_format) = expression.context.legend_format {
+        labels.extend(get_legend_label_refs(legend_format));
+    }
+
+    // invalid templates are reported by check-templates
+    for template in expression.context.annotations.values() {
+        labels.extend(get_template_label_refs(template).unwrap_or_default());
+    }
+
+    labels
+}
+
+/// `relabel-drops-metric` and `relabel-drops-label` findings for expressions selecting metrics
+/// or referring to labels the prediction removes.
+///
+/// Grouping, legend and annotation labels are not bound to a selector, they count for every
+/// metric of the expression.
+pub fn find_relabel_breakage(expressions: &[Expression], prediction: &Prediction) -> Vec<Finding> {
+    let mut findings = vec![];
+
+    for expression in expressions {
+        let ast = match parse(expression.expression.as_bytes()) {
+            Ok(ast) => ast,
+            Err(_) => continue,
+        };
+        let mut vectors = vec![];
+        get_vectors_from_node(&ast, &mut vectors);
+        let referenced_labels = get_referenced_labels(&ast, expression);
+
+        let mut dropped_metrics: BTreeSet<String> = BTreeSet::new();
+        let mut dropped_labels: BTreeSet<String> = BTreeSet::new();
+
+        for vector in vectors {
+            let metric = match vector.labels.iter().find(|label| label.name == "__name__") {
+                Some(label) => &label.value,
+                None => continue,
+            };
+
+            if prediction.dropped_metrics.contains(metric) {
+                dropped_metrics.insert(metric.clone());
+            }
+
+            if let Some(labels) = prediction.dropped_labels.get(metric) {
+                dropped_labels.extend(
+                    vector
+                        .labels
+                        .iter()
+                        .map(|label| &label.name)
+                        .chain(referenced_labels.iter())
+                        .filter(|name| labels.contains(*name))
+                        .map(|name| format!("{}/{}", metric, name)),
+                );
+            }
+        }
+
+        if !dropped_metrics.is_empty() {
+            let mut finding = Finding::new(
+                "relabel-drops-metric",
+                Severity::Error,
+                format!(
+                    "relabeling drops the metrics: {}",
+                    dropped_metrics.iter().cloned().collect::<Vec<_>>().join(", ")
+                ),
+                expression,
+            );
+            finding.metrics = dropped_metrics.into_iter().collect();
+            findings.push(finding);
+        }
+
+        if !dropped_labels.is_empty() {
+            findings.push(Finding::new(
+                "relabel-drops-label",
+                Severity::Error,
+                format!(
+                    "relabeling drops the used labels: {}",
+                    dropped_labels.into_iter().collect::<Vec<_>>().join(", ")
+                ),
+                expression,
+            ));
+        }
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> Labels {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn relabel(rules: &str, input: &[(&str, &str)]) -> Option<Labels> {
+        let rules: Vec<RelabelConfig> = serde_yaml::from_str(rules).expect("valid rules");
+        Relabeler::new(&rules)
+            .expect("valid regexes")
+            .apply(&labels(input))
+    }
+
+    #[test]
+    fn apply() {
+        let series = &[("__name__", "up"), ("job", "node"), ("pod", "a")];
+        let cases: Vec<(&str, Option<Labels>)> = vec![
+            (
+                "[{source_labels: [job], regex: node, action: keep}]",
+                Some(labels(series)),
+            ),
+            ("[{source_labels: [job], regex: api, action: keep}]", None),
+            // regexes are anchored
+            (
+                "[{source_labels: [job], regex: nod, action: drop}]",
+                Some(labels(series)),
+            ),
+            (
+                "[{source_labels: [__name__], regex: up, action: drop}]",
+                None,
+            ),
+            (
+                "[{regex: pod, action: labeldrop}]",
+                Some(labels(&[("__name__", "up"), ("job", "node")])),
+            ),
+            (
+                "[{regex: '(job|__name__)', action: labelkeep}]",
+                Some(labels(&[("__name__", "up"), ("job", "node")])),
+            ),
+            (
+                "[{regex: '(p.*)', replacement: 'k8s_$1', action: labelmap}]",
+                Some(labels(&[
+                    ("__name__", "up"),
+                    ("job", "node"),
+                    ("k8s_pod", "a"),
+                    ("pod", "a"),
+                ])),
+            ),
+            (
+                "[{source_labels: [pod], target_label: instance}]",
+                Some(labels(&[
+                    ("__name__", "up"),
+                    ("instance", "a"),
+                    ("job", "node"),
+                    ("pod", "a"),
+                ])),
+            ),
+            // an empty replacement removes the target
+            (
+                "[{target_label: pod, replacement: ''}]",
+                Some(labels(&[("__name__", "up"), ("job", "node")])),
+            ),
+            // internal labels are removed afterwards
+            (
+                "[{source_labels: [job], target_label: __tmp_job}]",
+                Some(labels(series)),
+            ),
+        ];
+
+        for (rules, expected) in cases {
+            assert_eq!(relabel(rules, series), expected, "{}", rules);
+        }
+    }
+
+    #[test]
+    fn missing_target_compares_as_empty() {
+        let series = &[("__name__", "up"), ("job", "node")];
+        let cases = vec![
+            (
+                "[{source_labels: [pod], target_label: node, action: keepequal}]",
+                true,
+            ),
+            (
+                "[{source_labels: [job], target_label: node, action: keepequal}]",
+                false,
+            ),
+            (
+                "[{source_labels: [pod], target_label: node, action: dropequal}]",
+                false,
+            ),
+            (
+                "[{source_labels: [job], target_label: node, action: dropequal}]",
+                true,
+            ),
+        ];
+
+        for (rules, kept) in cases {
+            assert_eq!(relabel(rules, series).is_some(), kept, "{}", rules);
+        }
+    }
+
+    #[test]
+    fn only_new_rules_are_applied() {
+        let old_config: PrometheusConfig = serde_yaml::from_str(
+            "scrape_configs:
+             - job_name: node
+               metric_relabel_configs: [{source_labels: [pod], target_label: instance}]
+             - job_name: api
+               metric_relabel_configs: [{source_labels: [pod], target_label: instance}]",
+        ).expect("valid config");
+        let config: PrometheusConfig = serde_yaml::from_str(
+            "scrape_configs:
+             - job_name: node
+               metric_relabel_configs: [{source_labels: [pod], target_label: instance}]
+             - job_name: api
+               relabel_configs: [{source_labels: [__meta_kubernetes_pod_name], target_label: pod}]
+               metric_relabel_configs:
+               - {source_labels: [pod], target_label: instance}
+               - {source_labels: [instance], regex: a, action: drop}
+             - job_name: db
+               metric_relabel_configs: [{regex: pod, action: labeldrop}]",
+        ).expect("valid config");
+
+        let relabelers = get_relabelers(&config, Some(&old_config)).expect("valid rules");
+        assert_eq!(relabelers.keys().collect::<Vec<_>>(), vec!["api", "db"]);
+
+        // the series carry the label the old rule set, it is not set again
+        let relabeler = &relabelers["api"];
+        assert_eq!(relabeler.rules.len(), 1);
+        assert_eq!(
+            relabeler.apply(&labels(&[("pod", "a"), ("instance", "a")])),
+            None
+        );
+        assert!(relabeler.apply(&labels(&[("pod", "a")])).is_some());
+
+        // without an old config every rule is predicted, except those reading internal labels
+        let relabelers = get_relabelers(&config, None).expect("valid rules");
+        assert_eq!(relabelers["node"].rules.len(), 1);
+        assert_eq!(relabelers["api"].rules.len(), 2);
+        assert_eq!(relabelers["db"].rules.len(), 1);
+    }
+}
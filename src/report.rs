@@ -0,0 +1,405 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use schemars::schema::RootSchema;
+
+use catalog::MetricCatalog;
+use usage::*;
+
+/// Bumped on incompatible changes of the report format, additions keep the version.
+///
+/// 2: findings about catalog metrics carry the catalog as `source` and set `job` and `metric`,
+/// instead of the job as source and the metric as expression.
+pub const REPORT_VERSION: u32 = 2;
+
+static VERSION: &'static str = env!("CARGO_PKG_VERSION");
+
+/// The result of a validation run.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct Report {
+    pub report_version: u32,
+    pub tool: Tool,
+    pub run: Run,
+    /// Sorted by name.
+    pub sources: Vec<Source>,
+    /// Sorted by source, pointer, job, metric, rule id and expression.
+    pub findings: Vec<Finding>,
+    pub summary: Summary,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct Tool {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct Run {
+    pub command: String,
+    /// Unix time in seconds.
+    pub started_at: u64,
+    /// Unix time in seconds.
+    pub finished_at: u64,
+}
+
+/// Where the validated expressions were read from.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct Source {
+    /// A url or a file path.
+    pub name: String,
+    pub expressions: usize,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct Finding {
+    /// What was checked, e.g. `unknown-metric`.
+    pub rule_id: String,
+    pub severity: Severity,
+    pub message: String,
+    /// The source of the expression.
+    pub source: String,
+    /// Path to the expression, e.g. dashboard, row, panel and target.
+    pub pointer: Vec<Pointer>,
+    /// Empty for findings about a metric of the catalog.
+    pub expression: String,
+    /// The job exposing the metric, for findings about a metric of the catalog.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job: Option<String>,
+    /// The metric of the catalog the finding is about.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metric: Option<String>,
+    /// The metrics the finding is about, sorted.
+    #[serde(default)]
+    pub metrics: Vec<String>,
+}
+
+impl Finding {
+    pub fn new(
+        rule_id: &str,
+        severity: Severity,
+        message: String,
+        expression: &Expression,
+    ) -> Finding {
+        Finding {
+            rule_id: rule_id.to_string(),
+            severity,
+            message,
+            source: expression.context.source.clone(),
+            pointer: expression.pointer.clone(),
+            expression: expression.expression.clone(),
+            job: None,
+            metric: None,
+            metrics: vec![],
+        }
+    }
+
+    /// A finding about a metric of the catalog `source` instead of an expression, `job` is the
+    /// job exposing the metric if the catalog knows it.
+    pub fn for_metric(
+        rule_id: &str,
+        severity: Severity,
+        message: String,
+        source: &str,
+        job: Option<&str>,
+        metric: &str,
+    ) -> Finding {
+        Finding {
+            rule_id: rule_id.to_string(),
+            severity,
+            message,
+            source: source.to_string(),
+            pointer: vec![],
+            expression: String::new(),
+            job: job.map(|job| job.to_string()),
+            metric: Some(metric.to_string()),
+            metrics: vec![metric.to_string()],
+        }
+    }
+
+    fn sort_key<'a>(&'a self) -> SortKey<'a> {
+        (
+            &self.source,
+            self.pointer
+                .iter()
+                .map(|pointer| (pointer.pointer_type.as_ref(), pointer.id))
+                .collect(),
+            &self.job,
+            &self.metric,
+            &self.rule_id,
+            &self.expression,
+        )
+    }
+}
+
+/// Source, pointer types and ids, job, metric, rule id and expression.
+type SortKey<'a> = (
+    &'a str,
+    Vec<(&'a str, u32)>,
+    &'a Option<String>,
+    &'a Option<String>,
+    &'a str,
+    &'a str,
+);
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default)]
+pub struct Summary {
+    pub expressions: usize,
+    pub findings: usize,
+    pub findings_by_severity: BTreeMap<Severity, usize>,
+    pub findings_by_rule: BTreeMap<String, usize>,
+}
+
+/// Collects sources and findings of a run, `finish` turns them into a report.
+pub struct ReportBuilder {
+    command: String,
+    started_at: SystemTime,
+    sources: BTreeMap<String, usize>,
+    findings: Vec<Finding>,
+}
+
+impl ReportBuilder {
+    pub fn new(command: &str) -> ReportBuilder {
+        ReportBuilder {
+            command: command.to_string(),
+            started_at: SystemTime::now(),
+            sources: BTreeMap::new(),
+            findings: vec![],
+        }
+    }
+
+    /// List a source even if it has no expressions.
+    pub fn add_source(&mut self, name: &str) {
+        self.sources.entry(name.to_string()).or_insert(0);
+    }
+
+    /// Count the expressions per source.
+    pub fn add_expressions(&mut self, expressions: &[Expression]) {
+        for expression in expressions {
+            *self
+                .sources
+                .entry(expression.context.source.clone())
+                .or_insert(0) += 1;
+        }
+    }
+
+    pub fn add_findings<I: IntoIterator<Item = Finding>>(&mut self, findings: I) {
+        self.findings.extend(findings);
+    }
+
+    pub fn finish(self) -> Report {
+        let mut findings = self.findings;
+        findings.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+
+        let mut summary = Summary {
+            expressions: self.sources.values().sum(),
+            findings: findings.len(),
+            ..Summary::default()
+        };
+        for finding in findings.iter() {
+            *summary
+                .findings_by_severity
+                .entry(finding.severity)
+                .or_insert(0) += 1;
+            *summary
+                .findings_by_rule
+                .entry(finding.rule_id.clone())
+                .or_insert(0) += 1;
+        }
+
+        Report {
+            report_version: REPORT_VERSION,
+            tool: Tool {
+                name: "promqueen".to_string(),
+                version: VERSION.to_string(),
+            },
+            run: Run {
+                command: self.command,
+                started_at: unix_time(self.started_at),
+                finished_at: unix_time(SystemTime::now()),
+            },
+            sources: self
+                .sources
+                .into_iter()
+                .map(|(name, expressions)| Source { name, expressions })
+                .collect(),
+            findings,
+            summary,
+        }
+    }
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs())
+        .unwrap_or(0)
+}
+
+/// The json schema of `Report`.
+pub fn report_schema() -> RootSchema {
+    schema_for!(Report)
+}
+
+/// `parse-error` findings for expressions which can not be parsed and `unknown-metric`
+/// findings for expressions using metrics missing in the catalog.
+pub fn validate_expressions(expressions: &[Expression], catalog: &MetricCatalog) -> Vec<Finding> {
+    expressions
+        .iter()
+        .filter_map(|expression| {
+            let metrics = match get_used_metrics_from_expression(&expression.expression) {
+                Ok(metrics) => metrics,
+                Err(err) => {
+                    return Some(Finding::new(
+                        "parse-error",
+                        Severity::Error,
+                        err.to_string(),
+                        expression,
+                    ))
+                }
+            };
+
+            let unknown: BTreeSet<String> = metrics
+                .into_iter()
+                .filter(|metric| !catalog.has_metric(metric))
+                .collect();
+
+            if unknown.is_empty() {
+                return None;
+            }
+
+            let mut finding = Finding::new(
+                "unknown-metric",
+                Severity::Error,
+                format!(
+                    "unknown metrics: {}",
+                    unknown.iter().cloned().collect::<Vec<_>>().join(", ")
+                ),
+                expression,
+            );
+            finding.metrics = unknown.into_iter().collect();
+
+            Some(finding)
+        }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finding(source: &str, pointer: &[(&str, u32)], rule_id: &str, expression: &str) -> Finding {
+        let expression = Expression {
+            pointer: pointer
+                .iter()
+                .map(|(pointer_type, id)| Pointer::new(*id, None, pointer_type))
+                .collect(),
+            expression: expression.to_string(),
+            context: ExpressionContext {
+                source: source.to_string(),
+                ..ExpressionContext::default()
+            },
+        };
+
+        Finding::new(rule_id, Severity::Error, String::new(), &expression)
+    }
+
+    fn metric(source: &str, job: Option<&str>, rule_id: &str, metric: &str) -> Finding {
+        Finding::for_metric(
+            rule_id,
+            Severity::Warning,
+            String::new(),
+            source,
+            job,
+            metric,
+        )
+    }
+
+    fn key(finding: &Finding) -> String {
+        let pointer: Vec<String> = finding
+            .pointer
+            .iter()
+            .map(|pointer| format!("{}{}", pointer.pointer_type, pointer.id))
+            .collect();
+
+        format!(
+            "{} {} {:?} {:?} {} {}",
+            finding.source,
+            pointer.join("/"),
+            finding.job,
+            finding.metric,
+            finding.rule_id,
+            finding.expression
+        )
+    }
+
+    #[test]
+    fn finish_sorts_findings() {
+        // in the expected order: by source, pointer types and ids, job, metric, rule id and
+        // expression
+        let sorted = vec![
+            finding(
+                "a.json",
+                &[("dashboard", 1), ("panel", 2)],
+                "unknown-metric",
+                "up",
+            ),
+            finding(
+                "a.json",
+                &[("dashboard", 1), ("panel", 10)],
+                "parse-error",
+                "up{",
+            ),
+            finding(
+                "a.json",
+                &[("dashboard", 1), ("panel", 10)],
+                "unknown-metric",
+                "a",
+            ),
+            finding(
+                "a.json",
+                &[("dashboard", 1), ("panel", 10)],
+                "unknown-metric",
+                "b",
+            ),
+            finding("b.yml", &[("alert", 0)], "unknown-metric", "up"),
+            finding(
+                "b.yml",
+                &[("group", 0), ("alert", 1)],
+                "unknown-metric",
+                "up",
+            ),
+            metric("catalog.json", None, "metric-name-unit", "b_ms"),
+            metric("catalog.json", Some("api"), "metric-name-case", "a"),
+            metric("catalog.json", Some("api"), "metric-name-unit", "a"),
+            metric("catalog.json", Some("api"), "metric-name-case", "b"),
+            metric("catalog.json", Some("node"), "metric-name-case", "a"),
+        ];
+        let expected: Vec<String> = sorted.iter().map(key).collect();
+
+        let mut reversed = sorted.clone();
+        reversed.reverse();
+        let mut rotated = sorted.clone();
+        rotated.rotate_left(3);
+        let cases = vec![sorted.clone(), reversed, rotated];
+
+        for findings in cases {
+            let input: Vec<String> = findings.iter().map(key).collect();
+            let mut builder = ReportBuilder::new("validate");
+            builder.add_findings(findings);
+            let report = builder.finish();
+
+            let actual: Vec<String> = report.findings.iter().map(key).collect();
+            assert_eq!(actual, expected, "{:?}", input);
+            assert_eq!(report.summary.findings, expected.len());
+            assert_eq!(report.summary.findings_by_rule["unknown-metric"], 5);
+            assert_eq!(report.summary.findings_by_rule["metric-name-case"], 3);
+            assert_eq!(report.summary.findings_by_rule["parse-error"], 1);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::cmp;
+use std::time::{Duration, Instant};
+
+use rand::{thread_rng, Rng};
+
+/// Exponential backoff with full jitter for idempotent requests.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt, `0` disables retries.
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Random delay in `[0, min(max_backoff, initial_backoff * 2^attempt)]`.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let max_millis = duration_millis(self.max_backoff);
+        let ceiling = duration_millis(self.initial_backoff)
+            .checked_mul(1 << cmp::min(attempt, 31))
+            .map(|millis| cmp::min(millis, max_millis))
+            .unwrap_or(max_millis);
+
+        Duration::from_millis(thread_rng().gen_range(0, ceiling + 1))
+    }
+}
+
+/// Status codes worth another attempt: rate limited or a (possibly temporary) server error.
+/// Errors like 501 Not Implemented will not go away by retrying.
+pub fn is_retryable_status(status: u16) -> bool {
+    match status {
+        429 | 500 | 502 | 503 | 504 => true,
+        _ => false,
+    }
+}
+
+fn duration_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
+
+/// One request every 1000 seconds, lower rates are most likely a typo.
+pub const MIN_REQUESTS_PER_SECOND: f64 = 0.001;
+
+/// Spaces requests evenly to stay below a fixed number of requests per second.
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next_slot: Option<Instant>,
+}
+
+impl RateLimiter {
+    /// Rates below `MIN_REQUESTS_PER_SECOND` (or NaN) are raised to it.
+    pub fn new(requests_per_second: f64) -> RateLimiter {
+        let rate = requests_per_second.max(MIN_REQUESTS_PER_SECOND);
+        let nanos = (1_000_000_000f64 / rate) as u64;
+
+        RateLimiter {
+            interval: Duration::from_nanos(nanos),
+            next_slot: None,
+        }
+    }
+
+    /// Reserve the next free slot and return how long to wait for it.
+    pub fn reserve(&mut self) -> Duration {
+        let now = Instant::now();
+        let slot = match self.next_slot {
+            Some(next_slot) if next_slot > now => next_slot,
+            _ => now,
+        };
+
+        self.next_slot = slot.checked_add(self.interval);
+
+        slot - now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(initial_millis: u64, max_millis: u64) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(initial_millis),
+            max_backoff: Duration::from_millis(max_millis),
+        }
+    }
+
+    #[test]
+    fn backoff() {
+        // (policy, attempt, ceiling in millis)
+        let cases = vec![
+            (policy(500, 10_000), 0, 500),
+            (policy(500, 10_000), 1, 1000),
+            (policy(500, 10_000), 4, 8000),
+            (policy(500, 10_000), 5, 10_000),
+            // the shift is capped and the multiplication must not overflow
+            (policy(500, 10_000), 100, 10_000),
+            (policy(1 << 62, 10_000), 3, 10_000),
+            (policy(0, 10_000), 3, 0),
+        ];
+
+        for case in cases {
+            let (policy, attempt, ceiling) = case.clone();
+            let ceiling = Duration::from_millis(ceiling);
+
+            for _ in 0..100 {
+                assert!(policy.backoff(attempt) <= ceiling, "{:?}", case);
+            }
+        }
+    }
+
+    #[test]
+    fn is_retryable() {
+        let cases = vec![
+            (200, false),
+            (400, false),
+            (404, false),
+            (429, true),
+            (500, true),
+            (501, false),
+            (502, true),
+            (503, true),
+            (504, true),
+            (505, false),
+        ];
+
+        for (status, expected) in cases {
+            assert_eq!(is_retryable_status(status), expected, "{}", status);
+        }
+    }
+
+    #[test]
+    fn rate_limiter() {
+        let cases = vec![
+            (2.0, Duration::from_millis(500)),
+            (1000.0, Duration::from_millis(1)),
+            // too low, NaN and infinite rates are clamped or do not wait
+            (0.0, Duration::from_secs(1000)),
+            (f64::NAN, Duration::from_secs(1000)),
+            (f64::INFINITY, Duration::from_secs(0)),
+        ];
+
+        for (rate, interval) in cases {
+            let mut limiter = RateLimiter::new(rate);
+
+            assert_eq!(limiter.reserve(), Duration::from_secs(0), "{}", rate);
+            let wait = limiter.reserve();
+            assert!(wait <= interval, "{}: {:?}", rate, wait);
+            assert!(
+                wait + Duration::from_millis(100) >= interval,
+                "{}: {:?}",
+                rate,
+                wait
+            );
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+
+/// A prometheus rule file, e.g. loaded with `rule_files` from `prometheus.yml`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RuleFile {
+    #[serde(default = "Vec::new")]
+    pub groups: Vec<RuleGroup>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RuleGroup {
+    pub name: String,
+    pub interval: Option<String>,
+    #[serde(default = "Vec::new")]
+    pub rules: Vec<Rule>,
+}
+
+/// An alerting rule (`alert` is set) or a recording rule (`record` is set).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Rule {
+    pub alert: Option<String>,
+    pub record: Option<String>,
+    pub expr: String,
+    #[serde(rename = "for")]
+    pub for_duration: Option<String>,
+    #[serde(default = "HashMap::new")]
+    pub labels: HashMap<String, String>,
+    #[serde(default = "HashMap::new")]
+    pub annotations: HashMap<String, String>,
+}
+
+impl Rule {
+    pub fn name(&self) -> Option<&str> {
+        self.alert
+            .as_ref()
+            .or_else(|| self.record.as_ref())
+            .map(|name| name.as_ref())
+    }
+
+    pub fn rule_type(&self) -> &'static str {
+        if self.alert.is_some() {
+            "alert"
+        } else {
+            "record"
+        }
+    }
+}
+
+pub fn parse_rule_file(content: &[u8]) -> Result<RuleFile, serde_yaml::Error> {
+    serde_yaml::from_slice(content)
+}
@@ -0,0 +1,71 @@
+use std::cell::Cell;
+
+use futures::sync::oneshot;
+use futures::{stream, Future, Stream};
+use tokio::runtime::{Builder, Runtime, TaskExecutor};
+
+thread_local! {
+    /// Set on the worker threads of the shared runtime.
+    static ON_RUNTIME: Cell<bool> = const { Cell::new(false) };
+}
+
+lazy_static! {
+    /// Shared by all clients so pooled connections outlive single calls. It is never dropped,
+    /// shutting it down from one of its own worker threads would block forever.
+    static ref RUNTIME: Runtime = Builder::new()
+        .after_start(|| ON_RUNTIME.with(|on_runtime| on_runtime.set(true)))
+        .build()
+        .expect("could not start the tokio runtime");
+}
+
+pub fn executor() -> TaskExecutor {
+    RUNTIME.executor()
+}
+
+/// Run `future` on the shared runtime and block the current thread until it resolves.
+///
+/// # Panics
+///
+/// On a worker thread of the shared runtime, e.g. in a future spawned on `executor()`. The
+/// worker would wait for a task it has to run itself, use the `fetch_*` futures there instead.
+pub fn block_on<F>(future: F) -> Result<F::Item, F::Error>
+where
+    F: Future + Send + 'static,
+    F::Item: Send + 'static,
+    F::Error: Send + 'static,
+{
+    if ON_RUNTIME.with(|on_runtime| on_runtime.get()) {
+        panic!("block_on called on a worker of the shared runtime, it would wait for itself");
+    }
+
+    let (tx, rx) = oneshot::channel();
+
+    executor().spawn(future.then(move |result| {
+        let _ = tx.send(result);
+        Ok(())
+    }));
+
+    rx.wait().expect("the runtime dropped a task")
+}
+
+/// Map every item to a future, with at most `concurrency` of them in flight.
+/// The results keep the order of `items`, so they match a sequential run.
+pub fn map_concurrent<T, F, R>(
+    items: Vec<T>,
+    concurrency: usize,
+    f: F,
+) -> Box<Future<Item = Vec<R::Item>, Error = R::Error> + Send>
+where
+    T: Send + 'static,
+    F: FnMut(T) -> R + Send + 'static,
+    R: Future + Send + 'static,
+    R::Item: Send + 'static,
+    R::Error: Send + 'static,
+{
+    Box::new(
+        stream::iter_ok(items)
+            .map(f)
+            .buffered(concurrency.max(1))
+            .collect(),
+    )
+}
@@ -0,0 +1,45 @@
+use std::net::SocketAddr;
+
+use futures::{future, Future};
+use hyper::header::CONTENT_TYPE;
+use hyper::service::service_fn;
+use hyper::{Body, Request, Response, Server, StatusCode};
+
+use errors::CliError;
+use runtime;
+
+pub type ResponseFuture = Box<Future<Item = Response<Body>, Error = hyper::Error> + Send>;
+
+/// Bind `addr` and serve `handler` on the shared runtime, returns as soon as the socket is bound.
+pub fn spawn<H>(addr: SocketAddr, handler: H) -> Result<(), CliError>
+where
+    H: Fn(Request<Body>) -> ResponseFuture + Clone + Send + Sync + 'static,
+{
+    // binding has to happen on the runtime to register the listener with its reactor
+    runtime::block_on(future::lazy(move || {
+        let server = Server::try_bind(&addr)?
+            .serve(move || {
+                let handler = handler.clone();
+                service_fn(move |request| handler(request))
+            }).map_err(|err| error!("server error: {}", err));
+
+        runtime::executor().spawn(server);
+        info!("listening on {}", addr);
+
+        Ok(())
+    })).map_err(CliError::ServerError)
+}
+
+pub fn respond(status: StatusCode, content_type: &str, body: String) -> ResponseFuture {
+    let response = Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, content_type)
+        .body(Body::from(body))
+        .expect("response parts are valid");
+
+    Box::new(future::ok(response))
+}
+
+pub fn not_found() -> ResponseFuture {
+    respond(StatusCode::NOT_FOUND, "text/plain", "not found\n".to_string())
+}
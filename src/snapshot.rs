@@ -0,0 +1,223 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use errors::*;
+use prometheus::{MetricMetadata, PrometheusClient};
+
+/// Bumped on incompatible changes of the snapshot format.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// The metric inventory of a prometheus at one point in time.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CatalogSnapshot {
+    pub version: u32,
+    /// The prometheus url the snapshot was taken from.
+    pub source: String,
+    /// Unix time of the export.
+    pub created_at: u64,
+    pub metrics: BTreeMap<String, MetricSnapshot>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct MetricSnapshot {
+    /// Label names with a sample of their values.
+    #[serde(default)]
+    pub labels: BTreeMap<String, BTreeSet<String>>,
+    #[serde(default)]
+    pub metadata: Vec<MetricMetadata>,
+    #[serde(default)]
+    pub series_count: usize,
+}
+
+impl CatalogSnapshot {
+    /// Metric names for `__name__`, otherwise the sampled values of the label.
+    pub fn get_label_values(&self, label: &str) -> Vec<String> {
+        if label == "__name__" {
+            return self.metrics.keys().cloned().collect();
+        }
+
+        let values: BTreeSet<&String> = self
+            .metrics
+            .values()
+            .filter_map(|metric| metric.labels.get(label))
+            .flat_map(|values| values.iter())
+            .collect();
+
+        values.into_iter().cloned().collect()
+    }
+}
+
+/// Export names, label names, sample label values, metadata and series counts of every metric.
+pub fn export_snapshot(
+    url: &str,
+    client: &PrometheusClient,
+    sample_values: usize,
+    concurrency: usize,
+) -> Result<CatalogSnapshot, CliError> {
+    let names = client
+        .get_label_values("__name__".to_string())
+        .map_err(CliError::ClientError)?;
+
+    // metadata is only available since prometheus v2.15
+    let mut metadata = client.get_metadata().unwrap_or_else(|err| {
+        warn!("could not get metric metadata: {:?}", err);
+        BTreeMap::new()
+    });
+
+    let matchers = names
+        .iter()
+        .map(|name| format!("{{__name__=\"{}\"}}", name))
+        .collect();
+    let series = client
+        .get_series(matchers, concurrency)
+        .map_err(CliError::ClientError)?;
+
+    let metrics = names
+        .into_iter()
+        .zip(series)
+        .map(|(name, series)| {
+            let mut labels: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+
+            for (label, value) in series.iter().flat_map(|labels| labels.iter()) {
+                if label == "__name__" {
+                    continue;
+                }
+
+                let values = labels.entry(label.clone()).or_default();
+                if values.len() < sample_values {
+                    values.insert(value.clone());
+                }
+            }
+
+            let snapshot = MetricSnapshot {
+                labels,
+                metadata: metadata.remove(&name).unwrap_or_default(),
+                series_count: series.len(),
+            };
+
+            (name, snapshot)
+        }).collect();
+
+    Ok(CatalogSnapshot {
+        version: SNAPSHOT_VERSION,
+        source: url.to_string(),
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_secs())
+            .unwrap_or(0),
+        metrics,
+    })
+}
+
+pub fn load_snapshot(path: &Path) -> Result<CatalogSnapshot, CliError> {
+    let file = File::open(path).map_err(|err| CliError::IoError(path.to_path_buf(), err))?;
+    let snapshot: CatalogSnapshot = serde_json::from_reader(file).map_err(CliError::SerdeError)?;
+
+    if snapshot.version != SNAPSHOT_VERSION {
+        return Err(CliError::UnsupportedSnapshotVersion(snapshot.version));
+    }
+
+    Ok(snapshot)
+}
+
+pub fn save_snapshot(path: &Path, snapshot: &CatalogSnapshot) -> Result<(), CliError> {
+    let file = File::create(path).map_err(|err| CliError::IoError(path.to_path_buf(), err))?;
+
+    serde_json::to_writer_pretty(file, snapshot).map_err(CliError::SerdeError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    fn metric(labels: &[(&str, &[&str])], series_count: usize) -> MetricSnapshot {
+        MetricSnapshot {
+            labels: labels
+                .iter()
+                .map(|(label, values)| {
+                    let values = values.iter().map(|value| value.to_string()).collect();
+                    (label.to_string(), values)
+                })
+                .collect(),
+            metadata: vec![],
+            series_count,
+        }
+    }
+
+    fn snapshot() -> CatalogSnapshot {
+        let mut up = metric(&[("job", &["node", "api"]), ("instance", &["a:9100"])], 2);
+        up.metadata.push(MetricMetadata {
+            metric_type: "gauge".to_string(),
+            help: "Whether the target is up.".to_string(),
+            unit: "".to_string(),
+        });
+
+        CatalogSnapshot {
+            version: SNAPSHOT_VERSION,
+            source: "http://prometheus:9090".to_string(),
+            created_at: 1_600_000_000,
+            metrics: vec![
+                ("up".to_string(), up),
+                (
+                    "scrape_duration_seconds".to_string(),
+                    metric(&[("job", &["db"])], 1),
+                ),
+                ("empty".to_string(), MetricSnapshot::default()),
+            ]
+            .into_iter()
+            .collect(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = env::temp_dir().join(format!("promqueen-snapshot-{}.json", process::id()));
+        let cases = vec![
+            (snapshot(), true),
+            (
+                CatalogSnapshot {
+                    version: SNAPSHOT_VERSION + 1,
+                    ..snapshot()
+                },
+                false,
+            ),
+        ];
+
+        for (snapshot, supported) in cases {
+            save_snapshot(&path, &snapshot).expect("writable snapshot");
+            let loaded = load_snapshot(&path);
+
+            if supported {
+                assert_eq!(loaded.expect("loadable snapshot"), snapshot);
+            } else {
+                match loaded {
+                    Err(CliError::UnsupportedSnapshotVersion(version)) => {
+                        assert_eq!(version, snapshot.version)
+                    }
+                    other => panic!("expected an unsupported version, got {:?}", other),
+                }
+            }
+        }
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn get_label_values() {
+        let cases = vec![
+            ("__name__", vec!["empty", "scrape_duration_seconds", "up"]),
+            ("job", vec!["api", "db", "node"]),
+            ("instance", vec!["a:9100"]),
+            ("pod", vec![]),
+        ];
+
+        for (label, expected) in cases {
+            assert_eq!(snapshot().get_label_values(label), expected, "{}", label);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::fs;
+use std::path::PathBuf;
+
+use errors::*;
+use grafana::{parse_dashboard, Dashboard, GrafanaClient};
+use prometheus::PrometheusClient;
+use rules::parse_rule_file;
+use usage::*;
+
+/// Anything promql expressions can be read from.
+pub trait ExpressionSource {
+    /// Identifies the source in the context of its expressions, e.g. a url or a file path.
+    fn name(&self) -> String;
+
+    /// Every expression of the source with its pointer path.
+    fn get_expressions(&self) -> Result<Vec<Expression>, CliError>;
+}
+
+/// The expressions of all sources, with the name of their source in the context.
+pub fn get_expressions_from_sources(
+    sources: &[Box<ExpressionSource>],
+) -> Result<Vec<Expression>, CliError> {
+    let mut expressions: Vec<Expression> = vec![];
+
+    for source in sources {
+        let name = source.name();
+
+        expressions.extend(source.get_expressions()?.into_iter().map(|mut expression| {
+            expression.context.source = name.clone();
+            expression
+        }));
+    }
+
+    Ok(expressions)
+}
+
+/// All dashboards of a grafana instance.
+pub struct GrafanaSource {
+    url: String,
+    client: GrafanaClient,
+    concurrency: usize,
+}
+
+impl GrafanaSource {
+    pub fn new(url: String, client: GrafanaClient, concurrency: usize) -> GrafanaSource {
+        GrafanaSource {
+            url,
+            client,
+            concurrency,
+        }
+    }
+}
+
+impl ExpressionSource for GrafanaSource {
+    fn name(&self) -> String {
+        self.url.clone()
+    }
+
+    fn get_expressions(&self) -> Result<Vec<Expression>, CliError> {
+        let dashboards = self
+            .client
+            .get_all_dashboards(self.concurrency)
+            .map_err(CliError::ClientError)?;
+
+        Ok(get_grafana_expressions(&self.url, &dashboards))
+    }
+}
+
+/// The expressions of dashboards read from the grafana at `url`, with absolute links.
+pub fn get_grafana_expressions(url: &str, dashboards: &[Dashboard]) -> Vec<Expression> {
+    dashboards
+        .iter()
+        .flat_map(get_expressions_from_dashboard)
+        .map(|mut expression| {
+            expression.set_base_url(url);
+            expression.context.source = url.to_string();
+            expression
+        }).collect()
+}
+
+/// A dashboard json file, as exported from grafana or returned by its api.
+pub struct DashboardFileSource {
+    path: PathBuf,
+}
+
+impl DashboardFileSource {
+    pub fn new(path: PathBuf) -> DashboardFileSource {
+        DashboardFileSource { path }
+    }
+}
+
+impl ExpressionSource for DashboardFileSource {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn get_expressions(&self) -> Result<Vec<Expression>, CliError> {
+        let content = fs::read(&self.path).map_err(|err| CliError::IoError(self.path.clone(), err))?;
+        let dashboard = parse_dashboard(&content).map_err(CliError::SerdeError)?;
+
+        Ok(get_expressions_from_dashboard(&dashboard))
+    }
+}
+
+/// The alerting and recording rules loaded by a prometheus, from its rules api.
+pub struct PrometheusRulesSource {
+    url: String,
+    client: PrometheusClient,
+}
+
+impl PrometheusRulesSource {
+    pub fn new(url: String, client: PrometheusClient) -> PrometheusRulesSource {
+        PrometheusRulesSource { url, client }
+    }
+}
+
+impl ExpressionSource for PrometheusRulesSource {
+    fn name(&self) -> String {
+        self.url.clone()
+    }
+
+    fn get_expressions(&self) -> Result<Vec<Expression>, CliError> {
+        let rule_file = self.client.get_rule_file().map_err(CliError::ClientError)?;
+
+        Ok(get_expressions_from_rule_file(&rule_file))
+    }
+}
+
+/// A prometheus rule yaml file.
+pub struct RuleFileSource {
+    path: PathBuf,
+}
+
+impl RuleFileSource {
+    pub fn new(path: PathBuf) -> RuleFileSource {
+        RuleFileSource { path }
+    }
+}
+
+impl ExpressionSource for RuleFileSource {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn get_expressions(&self) -> Result<Vec<Expression>, CliError> {
+        let content = fs::read(&self.path).map_err(|err| CliError::IoError(self.path.clone(), err))?;
+        let rule_file = parse_rule_file(&content)
+            .map_err(|err| CliError::RuleFileError(self.path.clone(), err))?;
+
+        Ok(get_expressions_from_rule_file(&rule_file))
+    }
+}
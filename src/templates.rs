@@ -0,0 +1,430 @@
+use std::collections::BTreeSet;
+
+use promql::parse;
+use regex::Regex;
+
+use output_labels::get_output_labels;
+use report::{Finding, Severity};
+use usage::*;
+
+lazy_static! {
+    /// A placeholder of a grafana legend format, like grafana matches them.
+    static ref LEGEND_PLACEHOLDER: Regex = Regex::new(r"\{\{\s*(.+?)\s*\}\}").unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    LeftParen,
+    RightParen,
+    Pipe,
+}
+
+/// The labels a go template of an annotation refers to with `$labels.name`, `.Labels.name` or
+/// `index $labels "name"`, or the first syntax error.
+///
+/// Only the syntax is checked, functions and their arguments are not.
+pub fn get_template_label_refs(text: &str) -> Result<BTreeSet<String>, String> {
+    let mut refs = BTreeSet::new();
+    let mut blocks: Vec<String> = vec![];
+    let mut offset = 0;
+
+    while let Some(start) = text[offset..].find("{{") {
+        let action_start = offset + start + 2;
+        let line = text[..action_start].matches('\n').count() + 1;
+        let at_line = |message: String| format!("line {}: {}", line, message);
+
+        let (tokens, length) = lex_action(&text[action_start..]).map_err(at_line)?;
+        offset = action_start + length;
+
+        let tokens = match tokens {
+            Some(tokens) => tokens,
+            None => continue,
+        };
+        check_action(&tokens, &mut blocks).map_err(at_line)?;
+        refs.extend(get_action_label_refs(&tokens));
+    }
+
+    match blocks.last() {
+        Some(block) => Err(format!("unexpected EOF, {{{{{}}}}} is not closed", block)),
+        None => Ok(refs),
+    }
+}
+
+/// The tokens of an action up to its `}}`, none for comments, and the length including the `}}`.
+fn lex_action(input: &str) -> Result<(Option<Vec<Token>>, usize), String> {
+    let mut tokens = vec![];
+    let mut chars = input.char_indices().peekable();
+
+    if input.trim_start_matches('-').trim_start().starts_with("/*") {
+        let comment_end = input
+            .find("*/")
+            .ok_or_else(|| "unclosed comment".to_string())?;
+        let rest = &input[comment_end + 2..];
+        let close = rest.trim_start_matches(" -");
+
+        if !close.starts_with("}}") {
+            return Err("comment ends before closing delimiter".to_string());
+        }
+
+        return Ok((None, input.len() - close.len() + 2));
+    }
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '}' if input[index..].starts_with("}}") => {
+                // trim markers are separated by spaces, otherwise they are negative numbers
+                let trim_marker = Token::Word("-".to_string());
+                if tokens.last() == Some(&trim_marker) {
+                    tokens.pop();
+                }
+                if tokens.first() == Some(&trim_marker) {
+                    tokens.remove(0);
+                }
+
+                return Ok((Some(tokens), index + 2));
+            }
+            c if c.is_whitespace() => (),
+            '(' => tokens.push(Token::LeftParen),
+            ')' => tokens.push(Token::RightParen),
+            '|' => tokens.push(Token::Pipe),
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, 'n')) => value.push('\n'),
+                            Some((_, 't')) => value.push('\t'),
+                            Some((_, escaped)) if escaped != '\n' => value.push(escaped),
+                            _ => return Err("unterminated quoted string".to_string()),
+                        },
+                        Some((_, '\n')) | None => {
+                            return Err("unterminated quoted string".to_string())
+                        }
+                        Some((_, c)) => value.push(c),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            '`' | '\'' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, end)) if end == c => break,
+                        Some((_, c)) => value.push(c),
+                        None if c == '`' => {
+                            return Err("unterminated raw quoted string".to_string())
+                        }
+                        None => return Err("unterminated character constant".to_string()),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(&(index, c)) = chars.peek() {
+                    if c.is_whitespace()
+                        || "()|\"`'".contains(c)
+                        || input[index..].starts_with("}}")
+                    {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+
+    Err("unclosed action".to_string())
+}
+
+/// Check the pipeline of an action and track the blocks opened and closed by it.
+fn check_action(tokens: &[Token], blocks: &mut Vec<String>) -> Result<(), String> {
+    let keyword = match tokens.first() {
+        Some(Token::Word(word)) => word.as_ref(),
+        Some(_) => "",
+        None => return Err("missing value for command".to_string()),
+    };
+
+    match keyword {
+        "if" | "range" | "with" | "define" | "block" => {
+            if tokens.len() < 2 {
+                return Err(format!("missing value for {}", keyword));
+            }
+            blocks.push(keyword.to_string());
+        }
+        "else" if blocks.is_empty() => return Err("unexpected {{else}}".to_string()),
+        "end" => {
+            if blocks.pop().is_none() {
+                return Err("unexpected {{end}}".to_string());
+            }
+            if tokens.len() > 1 {
+                return Err("unexpected token in end".to_string());
+            }
+        }
+        _ => (),
+    }
+
+    let mut depth = 0;
+    let mut expect_command = true;
+    for token in tokens {
+        match token {
+            Token::LeftParen => {
+                depth += 1;
+                expect_command = true;
+            }
+            Token::RightParen => {
+                if depth == 0 {
+                    return Err("unexpected right paren".to_string());
+                }
+                if expect_command {
+                    return Err("missing command".to_string());
+                }
+                depth -= 1;
+            }
+            Token::Pipe if expect_command => return Err("missing command".to_string()),
+            Token::Pipe => expect_command = true,
+            _ => expect_command = false,
+        }
+    }
+
+    if depth > 0 {
+        return Err("unclosed left paren".to_string());
+    }
+    if expect_command {
+        return Err("missing command".to_string());
+    }
+
+    Ok(())
+}
+
+fn get_action_label_refs(tokens: &[Token]) -> Vec<String> {
+    let mut refs = vec![];
+
+    for (index, token) in tokens.iter().enumerate() {
+        let word = match token {
+            Token::Word(word) => word,
+            _ => continue,
+        };
+
+        for prefix in ["$labels.", ".Labels."].iter() {
+            if let Some(path) = word.strip_prefix(prefix) {
+                let name = path.split('.').next().unwrap_or("");
+                if !name.is_empty() {
+                    refs.push(name.to_string());
+                }
+            }
+        }
+
+        if word == "index" {
+            if let (Some(Token::Word(map)), Some(Token::Str(name))) =
+                (tokens.get(index + 1), tokens.get(index + 2))
+            {
+                if map == "$labels" || map == ".Labels" {
+                    refs.push(name.clone());
+                }
+            }
+        }
+    }
+
+    refs
+}
+
+/// `template-error` findings for annotations of alerting rules which are no valid go
+/// templates and `template-unknown-label` findings for annotations referring to labels the
+/// expression can never return, e.g. `instance` after `sum by (job)`.
+///
+/// Templates only see the labels of the series, the labels of the rule are not among them.
+pub fn check_annotation_templates(expressions: &[Expression]) -> Vec<Finding> {
+    let mut findings = vec![];
+
+    for expression in expressions {
+        let rule_type = expression
+            .pointer
+            .last()
+            .map(|pointer| pointer.pointer_type.as_ref());
+        if rule_type != Some("alert") {
+            continue;
+        }
+
+        let output_labels = parse(expression.expression.as_bytes())
+            .ok()
+            .map(|ast| get_output_labels(&ast));
+
+        for (annotation, template) in expression.context.annotations.iter() {
+            let refs = match get_template_label_refs(template) {
+                Ok(refs) => refs,
+                Err(err) => {
+                    findings.push(Finding::new(
+                        "template-error",
+                        Severity::Error,
+                        format!("annotation {}: {}", annotation, err),
+                        expression,
+                    ));
+                    continue;
+                }
+            };
+
+            let output_labels = match output_labels {
+                Some(ref output_labels) => output_labels,
+                None => continue,
+            };
+            let unknown: Vec<String> = refs
+                .into_iter()
+                .filter(|label| output_labels.excludes(label))
+                .collect();
+
+            if !unknown.is_empty() {
+                findings.push(Finding::new(
+                    "template-unknown-label",
+                    Severity::Warning,
+                    format!(
+                        "annotation {} refers to labels the expression never returns: {}",
+                        annotation,
+                        unknown.join(", ")
+                    ),
+                    expression,
+                ));
+            }
+        }
+    }
+
+    findings
+}
+
+/// The labels the placeholders of a grafana legend format refer to.
+pub fn get_legend_label_refs(legend_format: &str) -> BTreeSet<String> {
+    LEGEND_PLACEHOLDER
+        .captures_iter(legend_format)
+        .map(|captures| captures[1].to_string())
+        .collect()
+}
+
+/// `legend-unknown-label` findings for dashboard targets whose legend format refers to labels
+/// the expression can never return, e.g. `{{pod}}` after `sum by (namespace)`.
+pub fn check_legend_formats(expressions: &[Expression]) -> Vec<Finding> {
+    expressions
+        .iter()
+        .filter_map(|expression| {
+            let legend_format = expression.context.legend_format.as_ref()?;
+            let ast = parse(expression.expression.as_bytes()).ok()?;
+            let output_labels = get_output_labels(&ast);
+
+            let unknown: Vec<String> = get_legend_label_refs(legend_format)
+                .into_iter()
+                .filter(|label| output_labels.excludes(label))
+                .collect();
+
+            if unknown.is_empty() {
+                return None;
+            }
+
+            Some(Finding::new(
+                "legend-unknown-label",
+                Severity::Warning,
+                format!(
+                    "legend format {:?} refers to labels the expression never returns: {}",
+                    legend_format,
+                    unknown.join(", ")
+                ),
+                expression,
+            ))
+        }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn refs(labels: &[&str]) -> Result<BTreeSet<String>, String> {
+        Ok(labels.iter().map(|label| label.to_string()).collect())
+    }
+
+    #[test]
+    fn get_template_label_refs() {
+        let cases = vec![
+            ("{{ $labels.instance }} is down", refs(&["instance"])),
+            (
+                "{{ .Labels.job }} on {{ $labels.node.name }}",
+                refs(&["job", "node"]),
+            ),
+            ("{{- $labels.pod -}} restarts", refs(&["pod"])),
+            ("{{ -1 }} {{ $value | humanizePercentage }}", refs(&[])),
+            (
+                "{{ index $labels \"x\" }} {{ index .Labels `y` }}",
+                refs(&["x", "y"]),
+            ),
+            ("{{ index $values \"x\" }}", refs(&[])),
+            ("{{/* $labels.commented */}}", refs(&[])),
+            ("{{- /* a comment */ -}}", refs(&[])),
+            (
+                "{{ if gt $value 0.5 }}{{ $labels.a }}{{ else }}{{ $labels.b }}{{ end }}",
+                refs(&["a", "b"]),
+            ),
+            (
+                "{{ range $i, $v := query \"up\" }}{{ $v.Labels.job }}{{ end }}",
+                refs(&[]),
+            ),
+            (
+                "{{ if $labels.a }}firing",
+                Err("unexpected EOF, {{if}} is not closed".to_string()),
+            ),
+            ("{{ end }}", Err("line 1: unexpected {{end}}".to_string())),
+            ("{{ else }}", Err("line 1: unexpected {{else}}".to_string())),
+            (
+                "a\n{{ $labels.a",
+                Err("line 2: unclosed action".to_string()),
+            ),
+            (
+                "{{ }}",
+                Err("line 1: missing value for command".to_string()),
+            ),
+            (
+                "{{ humanize ( }}",
+                Err("line 1: unclosed left paren".to_string()),
+            ),
+            ("{{ $value | }}", Err("line 1: missing command".to_string())),
+            (
+                "{{ \"open }}",
+                Err("line 1: unterminated quoted string".to_string()),
+            ),
+            (
+                "{{/* comment */ }}",
+                Err("line 1: comment ends before closing delimiter".to_string()),
+            ),
+            ("{{/* comment", Err("line 1: unclosed comment".to_string())),
+        ];
+
+        for (template, expected) in cases {
+            assert_eq!(
+                super::get_template_label_refs(template),
+                expected,
+                "{}",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn get_legend_label_refs() {
+        let cases = vec![
+            ("{{pod}}", vec!["pod"]),
+            ("{{ namespace }}/{{pod}} {{pod}}", vec!["namespace", "pod"]),
+            ("static", vec![]),
+        ];
+
+        for (legend_format, expected) in cases {
+            let expected: BTreeSet<String> = expected.into_iter().map(String::from).collect();
+            assert_eq!(
+                super::get_legend_label_refs(legend_format),
+                expected,
+                "{}",
+                legend_format
+            );
+        }
+    }
+}
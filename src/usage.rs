@@ -1,44 +1,110 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet};
 
+use catalog::MetricCatalog;
 use errors::*;
 use grafana::*;
 use prometheus::Alert;
-use promql::{parse, Node};
+use promql::{parse, Node, Op, Vector};
+use rules::RuleFile;
 
-/*
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub enum Identifier {
-    Numeric(u32),
-    String(String),
-}
-
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct Pointer {
-    pub id: Identifier,
-    pub name: Option<String>,
-    pub entity: String,
+    pub id: u32,
+    pub title: Option<String>,
+    #[serde(rename = "type")]
+    pub pointer_type: String,
+    /// The uid of a dashboard.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<String>,
+    /// The refId of a target.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ref_id: Option<String>,
+    /// The position of a panel in its row, set if the panel has no id and `id` falls back to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<u32>,
+    /// Link to the dashboard or panel, relative to grafana unless made absolute by `set_base_url`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub entity_url: Option<String>,
+    /// Link to explore the expression of a target against its datasource.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub view_url: Option<String>,
 }
-*/
 
+impl Pointer {
+    pub fn new(id: u32, title: Option<String>, pointer_type: &str) -> Pointer {
+        Pointer {
+            id,
+            title,
+            pointer_type: pointer_type.to_string(),
+            uid: None,
+            ref_id: None,
+            index: None,
+            entity_url: None,
+            view_url: None,
+        }
+    }
+
+    /// Prefix the relative links with the grafana url.
+    pub fn set_base_url(&mut self, base_url: &str) {
+        let base_url = base_url.trim_end_matches('/');
+
+        for url in self.entity_url.iter_mut().chain(self.view_url.iter_mut()) {
+            if url.starts_with('/') {
+                *url = format!("{}{}", base_url, url);
+            }
+        }
+    }
+}
+
+/// What is known about an expression besides its pointer.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ExpressionContext {
+    /// The source the expression was read from, e.g. a url or a file path.
+    #[serde(default)]
+    pub source: String,
+    /// The labels of the rule, empty for dashboard targets.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// The annotations of an alerting rule.
+    #[serde(default)]
+    pub annotations: BTreeMap<String, String>,
+    /// The `for` duration of an alerting rule.
+    #[serde(rename = "for", default, skip_serializing_if = "Option::is_none")]
+    pub for_duration: Option<String>,
+    /// The evaluation interval of the rule group, the global one if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval: Option<String>,
+    /// The legend format of a dashboard target.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub legend_format: Option<String>,
+}
+
+/// A promql expression and where it was found.
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct Pointer {
-    pub id: u32,
-    pub title: Option<String>,
-    #[serde(rename = "type")]
-    pub pointer_type: String,
+pub struct Expression {
+    pub pointer: Vec<Pointer>,
+    pub expression: String,
+    #[serde(default)]
+    pub context: ExpressionContext,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl Expression {
+    /// See `Pointer::set_base_url`.
+    pub fn set_base_url(&mut self, base_url: &str) {
+        for pointer in self.pointer.iter_mut() {
+            pointer.set_base_url(base_url);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MetricsUsage {
     pub pointer: Vec<Pointer>,
     pub expression: String,
-    pub metrics: HashSet<String>,
+    pub metrics: BTreeSet<String>,
 }
 
-pub fn get_used_metrics_from_node(ast: &Node, metrics: &mut HashSet<String>) {
+pub fn get_used_metrics_from_node(ast: &Node, metrics: &mut BTreeSet<String>) {
     match ast {
         Node::Operator { x, y, .. } => {
             get_used_metrics_from_node(x, metrics);
@@ -63,85 +129,258 @@ pub fn get_used_metrics_from_node(ast: &Node, metrics: &mut HashSet<String>) {
     }
 }
 
-pub fn get_used_metrics_from_dashboard(dashboard: Dashboard) -> Vec<MetricsUsage> {
-    let dashboard_pointer = Pointer {
-        id: dashboard.id as u32,
-        title: Some(dashboard.title.clone()),
-        pointer_type: "dashboard".to_string(),
-    };
-
-    let mut usages: Vec<MetricsUsage> = vec![];
-
-    for (row_id, row) in dashboard.rows.iter().enumerate() {
-        let row_pointer = Pointer {
-            id: row_id as u32,
-            title: Some(row.title.clone()),
-            pointer_type: "row".to_string(),
-        };
-        for (panel_id, panel) in row.panels.iter().enumerate() {
-            let panel_pointer = Pointer {
-                id: panel_id as u32,
-                title: Some(panel.title.clone()),
-                pointer_type: "panel".to_string(),
-            };
-            if panel.datasource.clone().unwrap_or("#empty#".to_string()) != "Prometheus" {
-                continue;
+/// Every vector selector of the expression, in order of appearance.
+pub fn get_vectors_from_node<'a>(ast: &'a Node, vectors: &mut Vec<&'a Vector>) {
+    match ast {
+        Node::Operator { x, y, .. } => {
+            get_vectors_from_node(x, vectors);
+            get_vectors_from_node(y, vectors);
+        }
+        Node::Vector(vec) => vectors.push(vec),
+        Node::Function { args, .. } => {
+            for node in args {
+                get_vectors_from_node(node, vectors);
             }
-            for (target_id, target) in panel.targets.iter().enumerate() {
-                if !target.expr.is_some() {
-                    continue;
+        }
+        Node::Negation(node) => {
+            get_vectors_from_node(node, vectors);
+        }
+        _ => (),
+    }
+}
+
+/// The labels the expression groups or matches series by: the labels of `by`/`without`
+/// aggregations and of `on`, `ignoring` and `group_left`/`group_right` vector matching.
+pub fn get_grouping_labels_from_node(ast: &Node, labels: &mut BTreeSet<String>) {
+    match ast {
+        Node::Operator { x, op, y } => {
+            let op_mod = match op {
+                Op::Pow(op_mod)
+                | Op::Mul(op_mod)
+                | Op::Div(op_mod)
+                | Op::Mod(op_mod)
+                | Op::Plus(op_mod)
+                | Op::Minus(op_mod)
+                | Op::Eq(_, op_mod)
+                | Op::Ne(_, op_mod)
+                | Op::Lt(_, op_mod)
+                | Op::Gt(_, op_mod)
+                | Op::Le(_, op_mod)
+                | Op::Ge(_, op_mod)
+                | Op::And(op_mod)
+                | Op::Unless(op_mod)
+                | Op::Or(op_mod) => op_mod,
+            };
+
+            if let Some(op_mod) = op_mod {
+                labels.extend(op_mod.labels.iter().cloned());
+                if let Some(ref group) = op_mod.group {
+                    labels.extend(group.labels.iter().cloned());
                 }
-                let target_pointer = Pointer {
-                    id: target_id as u32,
-                    title: target.title.clone(),
-                    pointer_type: "target".to_string(),
-                };
+            }
 
-                let expression = target.expr.clone().unwrap();
+            get_grouping_labels_from_node(x, labels);
+            get_grouping_labels_from_node(y, labels);
+        }
+        Node::Function {
+            args, aggregation, ..
+        } => {
+            if let Some(aggregation) = aggregation {
+                labels.extend(aggregation.labels.iter().cloned());
+            }
 
-                let ast = match parse(expression.as_bytes()) {
-                    Ok(node) => node,
-                    _ => continue,
-                };
+            for node in args {
+                get_grouping_labels_from_node(node, labels);
+            }
+        }
+        Node::Negation(node) => {
+            get_grouping_labels_from_node(node, labels);
+        }
+        _ => (),
+    }
+}
 
-                let mut metrics = HashSet::new();
-                get_used_metrics_from_node(&ast, &mut metrics);
+/// Every prometheus expression of the dashboard, pointing to dashboard, row, panel and target.
+pub fn get_expressions_from_dashboard(dashboard: &Dashboard) -> Vec<Expression> {
+    let mut dashboard_pointer =
+        Pointer::new(dashboard.id, Some(dashboard.title.clone()), "dashboard");
+    dashboard_pointer.uid = dashboard.uid.clone();
+    dashboard_pointer.entity_url = dashboard.uid.as_ref().map(|uid| dashboard_path(uid));
+
+    let mut expressions: Vec<Expression> = vec![];
+
+    for (row_id, (row_title, panels)) in dashboard.get_rows().into_iter().enumerate() {
+        let row_pointer = Pointer::new(row_id as u32, Some(row_title.to_string()), "row");
+        for (panel_index, panel) in panels.into_iter().enumerate() {
+            // panels of grafana v4 dashboards may lack an id
+            let mut panel_pointer = Pointer::new(
+                panel.id.unwrap_or(panel_index as u32),
+                Some(panel.title.clone()),
+                "panel",
+            );
+            if panel.id.is_none() {
+                panel_pointer.index = Some(panel_index as u32);
+            }
+            if let (Some(uid), Some(panel_id)) = (dashboard.uid.as_ref(), panel.id) {
+                panel_pointer.entity_url = Some(panel_path(uid, panel_id));
+            }
+            let is_prometheus = match panel.datasource {
+                Some(ref datasource) => datasource.is_prometheus(),
+                None => false,
+            };
+            if !is_prometheus {
+                continue;
+            }
+            for (target_id, target) in panel.targets.iter().enumerate() {
+                let expr = match target.expr {
+                    Some(ref expr) => expr,
+                    None => continue,
+                };
+                let mut target_pointer =
+                    Pointer::new(target_id as u32, target.title.clone(), "target");
+                target_pointer.ref_id = target.ref_id.clone();
+                target_pointer.view_url = panel
+                    .datasource
+                    .as_ref()
+                    .and_then(|datasource| datasource.key())
+                    .map(|datasource| explore_path(datasource, expr, target.ref_id.as_ref()));
 
-                let usage = MetricsUsage {
+                expressions.push(Expression {
                     pointer: vec![
                         dashboard_pointer.clone(),
                         row_pointer.clone(),
                         panel_pointer.clone(),
                         target_pointer.clone(),
                     ],
-                    expression: expression,
-                    //ast: ast,
-                    metrics: metrics,
-                };
-
-                usages.push(usage);
+                    expression: expr.clone(),
+                    context: ExpressionContext {
+                        legend_format: target.legend_format.clone(),
+                        ..ExpressionContext::default()
+                    },
+                });
             }
         }
     }
 
-    usages
+    expressions
+}
+
+pub fn get_used_metrics_from_dashboard(dashboard: Dashboard) -> Vec<MetricsUsage> {
+    get_used_metrics_from_expressions(&get_expressions_from_dashboard(&dashboard))
+}
+
+/// The expressions `get_used_metrics_from_dashboard` skips, because they can not be parsed.
+pub fn get_parse_errors_from_dashboard(dashboard: &Dashboard) -> Vec<Expression> {
+    get_parse_errors(get_expressions_from_dashboard(dashboard))
+}
+
+/// Every rule expression of the file, pointing to group and rule.
+pub fn get_expressions_from_rule_file(rule_file: &RuleFile) -> Vec<Expression> {
+    let mut expressions: Vec<Expression> = vec![];
+
+    for (group_id, group) in rule_file.groups.iter().enumerate() {
+        let group_pointer = Pointer::new(group_id as u32, Some(group.name.clone()), "group");
+
+        for (rule_id, rule) in group.rules.iter().enumerate() {
+            let rule_pointer = Pointer::new(
+                rule_id as u32,
+                rule.name().map(|name| name.to_string()),
+                rule.rule_type(),
+            );
+
+            expressions.push(Expression {
+                pointer: vec![group_pointer.clone(), rule_pointer],
+                expression: rule.expr.clone(),
+                context: ExpressionContext {
+                    source: String::new(),
+                    labels: rule
+                        .labels
+                        .iter()
+                        .map(|(name, value)| (name.clone(), value.clone()))
+                        .collect(),
+                    annotations: rule
+                        .annotations
+                        .iter()
+                        .map(|(name, value)| (name.clone(), value.clone()))
+                        .collect(),
+                    for_duration: rule.for_duration.clone(),
+                    interval: group.interval.clone(),
+                    legend_format: None,
+                },
+            });
+        }
+    }
+
+    expressions
+}
+
+/// Parse every expression, expressions which can not be parsed are skipped.
+pub fn get_used_metrics_from_expressions(expressions: &[Expression]) -> Vec<MetricsUsage> {
+    expressions
+        .iter()
+        .filter_map(|expression| {
+            let metrics = match get_used_metrics_from_expression(&expression.expression) {
+                Ok(metrics) => metrics,
+                _ => return None,
+            };
+
+            Some(MetricsUsage {
+                pointer: expression.pointer.clone(),
+                expression: expression.expression.clone(),
+                metrics: metrics,
+            })
+        }).collect()
+}
+
+pub fn get_parse_errors(expressions: Vec<Expression>) -> Vec<Expression> {
+    expressions
+        .into_iter()
+        .filter(|expression| parse(expression.expression.as_bytes()).is_err())
+        .collect()
+}
+
+pub fn get_used_metrics_from_expression(expression: &str) -> Result<BTreeSet<String>, CliError> {
+    let ast = parse(expression.as_bytes())
+        .map_err(|_err| CliError::PromqlError(expression.to_string(), None))?;
+
+    let mut metrics = BTreeSet::new();
+    get_used_metrics_from_node(&ast, &mut metrics);
+
+    Ok(metrics)
+}
+
+/// The alert of an alerting rule expression, `None` for other expressions.
+pub fn get_alert(expression: &Expression) -> Option<Alert> {
+    let rule = expression.pointer.last()?;
+    if rule.pointer_type != "alert" {
+        return None;
+    }
+
+    let group = expression
+        .pointer
+        .iter()
+        .rev()
+        .find(|pointer| pointer.pointer_type == "group")
+        .and_then(|pointer| pointer.title.clone());
+    let context = &expression.context;
+
+    Some(Alert {
+        name: rule.title.clone().unwrap_or_default(),
+        expression: expression.expression.clone(),
+        labels: context.labels.clone().into_iter().collect(),
+        annotations: context.annotations.clone().into_iter().collect(),
+        for_duration: context.for_duration.clone(),
+        group,
+    })
 }
 
 pub fn get_used_metrics_from_alert(alert: Alert) -> Result<MetricsUsage, CliError> {
     let expression: String = alert.expression.to_owned();
     let name = alert.name.to_owned();
 
-    let ast = parse(alert.expression.as_bytes())
-        .map_err(|_err| CliError::PromqlError(expression.clone(), None))?;
+    let metrics = get_used_metrics_from_expression(&expression)?;
 
-    let mut metrics = HashSet::new();
-    get_used_metrics_from_node(&ast, &mut metrics);
-
-    let pointer = Pointer {
-        id: 0,
-        title: Some(name),
-        pointer_type: "alert".to_string(),
-    };
+    let pointer = Pointer::new(0, Some(name), "alert");
 
     Ok(MetricsUsage {
         pointer: vec![pointer],
@@ -149,3 +388,24 @@ pub fn get_used_metrics_from_alert(alert: Alert) -> Result<MetricsUsage, CliErro
         metrics,
     })
 }
+
+/// Reduce every usage to the metrics missing in the catalog, usages without any are dropped.
+pub fn get_invalid_usages(usages: &[MetricsUsage], catalog: &MetricCatalog) -> Vec<MetricsUsage> {
+    usages
+        .iter()
+        .map(|usage| {
+            let invalid_metrics: BTreeSet<_> = usage
+                .metrics
+                .iter()
+                .filter(|metric| !catalog.has_metric(metric))
+                .map(|metric| metric.to_owned())
+                .collect();
+
+            MetricsUsage {
+                pointer: usage.pointer.clone(),
+                expression: usage.expression.clone(),
+                metrics: invalid_metrics,
+            }
+        }).filter(|usage| usage.metrics.len() > 0)
+        .collect()
+}